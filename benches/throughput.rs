@@ -0,0 +1,59 @@
+//! Measures rows/sec for inserts and full scans, run with `cargo bench`.
+//!
+//! Each case is repeated until it has run for at least `MIN_DURATION` and the mean is reported.
+
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use dbrs::row::Row;
+use dbrs::table::Table;
+use dbrs::util::user_row;
+
+const SIZES: [usize; 3] = [1_000, 10_000, 100_000];
+const MIN_DURATION: Duration = Duration::from_millis(500);
+
+fn main() {
+    for num_rows in SIZES {
+        let rows: Vec<Row> = (0..num_rows as u32)
+            .map(user_row)
+            .collect();
+
+        bench("Row::serialize", num_rows, || rows.iter().map(|row| row.serialize()[0]).fold(0u8, u8::wrapping_add));
+
+        bench("insert_row", num_rows, || {
+            let mut table = Table::new().with_max_pages(usize::MAX);
+            for row in rows.iter() {
+                table.insert_row(row).unwrap();
+            }
+            table
+        });
+
+        bench("insert_row (file)", num_rows, || {
+            let path = std::env::temp_dir().join(format!("dbrs-bench-{}.db", std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            let mut table = Table::open(&path).unwrap().with_max_pages(usize::MAX);
+            for row in rows.iter() {
+                table.insert_row(row).unwrap();
+            }
+            table.flush().unwrap();
+            std::fs::remove_file(&path).unwrap();
+        });
+
+        let mut table = Table::new().with_max_pages(usize::MAX);
+        table.insert_rows(&rows).unwrap();
+        bench("iter().count()", num_rows, || table.iter().count());
+    }
+}
+
+fn bench<T>(name: &str, num_rows: usize, mut f: impl FnMut() -> T) {
+    let mut iterations = 0u32;
+    let start = Instant::now();
+    while start.elapsed() < MIN_DURATION {
+        black_box(f());
+        iterations += 1;
+    }
+    let per_iteration = start.elapsed() / iterations;
+    let rows_per_sec = num_rows as f64 / per_iteration.as_secs_f64();
+
+    println!("{:<20} {:>7} rows: {:>10.3?}/iter {:>14.0} rows/sec", name, num_rows, per_iteration, rows_per_sec);
+}
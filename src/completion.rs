@@ -0,0 +1,74 @@
+/// Statement keywords and meta commands offered for completion
+pub const KEYWORDS: &[&str] = &[
+    "insert", "select", "delete", "update", "explain", "create", "drop",
+    ".backup", ".changes", ".close", ".compact", ".echo", ".exit", ".fill", ".help", ".import", ".indexes", ".mode", ".open", ".pagesize",
+    ".prompt", ".restore", ".row", ".schema", ".stats", ".timer", ".truncate", ".verbose",
+];
+
+/// Completes the first word of a line from a list of keywords
+#[derive(Debug)]
+pub struct Completer {
+    keywords: &'static [&'static str],
+}
+
+impl Completer {
+    pub fn new(keywords: &'static [&'static str]) -> Self {
+        Completer { keywords }
+    }
+
+    /// Returns the keywords starting with `line`, ignoring case. Only the first word of a line is
+    /// completed, so there are no candidates once `line` contains whitespace.
+    pub fn complete(&self, line: &str) -> Vec<&'static str> {
+        let prefix = line.trim_start().to_lowercase();
+        if prefix.contains(char::is_whitespace) {
+            return Vec::new();
+        }
+        self.keywords.iter()
+            .copied()
+            .filter(|keyword| keyword.starts_with(prefix.as_str()))
+            .collect()
+    }
+}
+
+impl Default for Completer {
+    fn default() -> Self {
+        Completer::new(KEYWORDS)
+    }
+}
+
+/// The longest prefix shared by all `candidates`
+pub fn common_prefix<'a>(candidates: &[&'a str]) -> &'a str {
+    let first = match candidates.first() {
+        Some(first) => *first,
+        None => return "",
+    };
+    let len = candidates[1..].iter().fold(first.len(), |len, candidate| {
+        first.bytes().zip(candidate.bytes()).take(len).take_while(|(a, b)| a == b).count()
+    });
+    &first[..len]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::completion::{common_prefix, Completer};
+
+    #[test]
+    fn completes_prefixes() {
+        let completer = Completer::default();
+        assert_eq!(completer.complete("sel"), ["select"]);
+        assert_eq!(completer.complete("SEL"), ["select"]);
+        assert_eq!(completer.complete(".e"), [".echo", ".exit"]);
+        assert_eq!(completer.complete(".ex"), [".exit"]);
+        assert_eq!(completer.complete(".tr"), [".truncate"]);
+        assert_eq!(completer.complete(".").len(), 21);
+        assert!(completer.complete("x").is_empty());
+        assert!(completer.complete("select ").is_empty());
+    }
+
+    #[test]
+    fn common_prefix_of_candidates() {
+        assert_eq!(common_prefix(&[".open", ".exit"]), ".");
+        assert_eq!(common_prefix(&["select"]), "select");
+        assert_eq!(common_prefix(&[]), "");
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::error::DbError;
+use crate::row::Row;
+use crate::table::{Table, TableIterator};
+
+/// A table that can be cloned and shared between threads.
+///
+/// Any number of readers can run at the same time, inserts wait for them and run exclusively. Rows are
+/// always copied out of the pages while the lock is held: page buffers, e.g. a `RowRef`, must never be
+/// handed out across the lock boundary because a writer may modify them as soon as the lock is released.
+#[derive(Debug, Clone)]
+pub struct SharedTable {
+    table: Arc<RwLock<Table>>,
+}
+
+impl SharedTable {
+    pub fn new(table: Table) -> Self {
+        SharedTable {
+            table: Arc::new(RwLock::new(table)),
+        }
+    }
+
+    pub fn insert(&self, row: &Row) -> Result<usize, DbError> {
+        self.write().insert_row(row)
+    }
+
+    pub fn select_by_id(&self, id: u32) -> Result<Option<Row>, DbError> {
+        self.read().select_by_id(id)
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.read().num_rows()
+    }
+
+    /// Runs `f` on the table while holding a read lock, what it returns cannot borrow from the table
+    pub fn read_with<T>(&self, f: impl FnOnce(&Table) -> T) -> T {
+        f(&self.read())
+    }
+
+    /// Runs `f` on the table while holding the write lock
+    pub fn write_with<T>(&self, f: impl FnOnce(&mut Table) -> T) -> T {
+        f(&mut self.write())
+    }
+
+    /// Takes a read lock that is held until the snapshot is dropped, inserts block in the meantime
+    pub fn snapshot(&self) -> Snapshot<'_> {
+        Snapshot { table: self.read() }
+    }
+
+    // inserts check everything before they modify the table, so a panicking thread cannot leave it half-written
+    fn read(&self) -> RwLockReadGuard<'_, Table> {
+        self.table.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Table> {
+        self.table.write().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// A consistent view of a `SharedTable` while a read lock is held
+pub struct Snapshot<'a> {
+    table: RwLockReadGuard<'a, Table>,
+}
+
+impl<'a> Snapshot<'a> {
+    pub fn num_rows(&self) -> usize {
+        self.table.num_rows()
+    }
+
+    pub fn iter(&self) -> TableIterator<'_> {
+        self.table.iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+
+    use crate::concurrency::SharedTable;
+    use crate::table::Table;
+    use crate::util::user_row;
+
+    #[test]
+    fn readers_and_writer() -> Result<(), String> {
+        let shared = SharedTable::new(Table::new());
+        let num_inserts = 500;
+
+        let writer = {
+            let shared = shared.clone();
+            thread::spawn(move || {
+                for id in 0..num_inserts {
+                    shared.insert(&user_row(id)).unwrap();
+                }
+            })
+        };
+
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let shared = shared.clone();
+                thread::spawn(move || {
+                    let mut last_count = 0;
+                    while last_count < num_inserts as usize {
+                        let snapshot = shared.snapshot();
+                        let count = snapshot.num_rows();
+                        assert!(count >= last_count);
+
+                        // every row of the snapshot is complete, rows are inserted in id order
+                        for (position, row) in snapshot.iter().enumerate() {
+                            assert_eq!(row.id as usize, position);
+                            assert_eq!(row.username, format!("user{}", position));
+                        }
+                        last_count = count;
+                    }
+                })
+            })
+            .collect();
+
+        writer.join().unwrap();
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        assert_eq!(shared.num_rows(), num_inserts as usize);
+        assert_eq!(shared.select_by_id(42)?.unwrap().username, "user42");
+        assert!(shared.select_by_id(num_inserts)?.is_none());
+
+        Ok(())
+    }
+}
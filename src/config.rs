@@ -0,0 +1,195 @@
+use std::env;
+use std::path::PathBuf;
+
+const STRICT_FLAG: &str = "--strict";
+const IN_MEMORY_FLAG: &str = "--in-memory";
+const PAGE_SIZE_FLAG: &str = "--page-size";
+const CACHE_SIZE_FLAG: &str = "--cache-size";
+const SCRIPT_FLAG: &str = "-f";
+const RECOVER_FLAG: &str = "--recover";
+const READONLY_FLAG: &str = "--readonly";
+const PROMPT_FLAG: &str = "--prompt";
+const QUIET_FLAG: &str = "--quiet";
+const NO_PROMPT_FLAG: &str = "--no-prompt";
+const PAGE_SIZE_VAR: &str = "DBRS_PAGE_SIZE";
+const CACHE_SIZE_VAR: &str = "DBRS_CACHE_SIZE";
+const DB_PATH_VAR: &str = "DBRS_DB_PATH";
+const PROMPT_VAR: &str = "DBRS_PROMPT";
+
+/// How the database is opened, as given on the command line or by environment variables
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    /// The database file, `None` for an in-memory table
+    pub path: Option<PathBuf>,
+    /// The table is explicitly kept in memory, which rules out a path
+    pub in_memory: bool,
+    /// Page size in bytes of new database files, an existing file must use the same size
+    pub page_size: Option<usize>,
+    /// Number of pages kept in memory. The storage keeps every page of a table in memory, so this also
+    /// bounds the number of pages the table may grow to.
+    pub cache_size: Option<usize>,
+    /// Exit with a nonzero code at the first failing statement
+    pub strict: bool,
+    /// Run the lines of this file instead of reading stdin
+    pub script: Option<PathBuf>,
+    /// Open a truncated file by dropping its incomplete last page instead of failing
+    pub recover: bool,
+    /// Open the file for reading only, rejecting every change
+    pub read_only: bool,
+    /// The prompt shown before every line, `None` for the default `db> `
+    pub prompt: Option<String>,
+    /// Print only query results and errors, without prompts or confirmations of changes
+    pub quiet: bool,
+}
+
+impl Config {
+    /// Parses the command line arguments without the program name. Flags taking a value accept it as the
+    /// next argument or after an `=`, e.g. `--page-size 8192` or `--page-size=8192`.
+    pub fn from_args<I, S>(args: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        let mut config = Config::default();
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let arg = arg.as_ref();
+            let (flag, inline_value) = match arg.split_once('=') {
+                Some((flag, value)) if flag.starts_with("--") => (flag, Some(value.to_string())),
+                _ => (arg, None),
+            };
+            let mut value = || {
+                inline_value.clone()
+                    .or_else(|| args.next().map(|value| value.as_ref().to_string()))
+                    .ok_or_else(|| format!("Missing value for {}", flag))
+            };
+
+            match flag {
+                STRICT_FLAG => config.strict = true,
+                IN_MEMORY_FLAG => config.in_memory = true,
+                RECOVER_FLAG => config.recover = true,
+                READONLY_FLAG => config.read_only = true,
+                QUIET_FLAG | NO_PROMPT_FLAG => config.quiet = true,
+                PAGE_SIZE_FLAG => config.page_size = Some(parse_size(flag, &value()?)?),
+                CACHE_SIZE_FLAG => config.cache_size = Some(parse_size(flag, &value()?)?),
+                SCRIPT_FLAG => config.script = Some(PathBuf::from(value()?)),
+                PROMPT_FLAG => config.prompt = Some(value()?),
+                _ if flag.starts_with('-') => return Err(format!("Unknown option '{}'", arg)),
+                _ if config.path.is_some() => return Err(format!("Unexpected argument '{}', only one database file can be opened", arg)),
+                _ => config.path = Some(PathBuf::from(arg)),
+            }
+        }
+
+        if config.in_memory && config.path.is_some() {
+            return Err(format!("{} cannot be combined with a database file", IN_MEMORY_FLAG));
+        }
+        Ok(config)
+    }
+
+    /// Parses the command line arguments like `from_args`, taking the settings they leave out from the
+    /// `DBRS_PAGE_SIZE`, `DBRS_CACHE_SIZE`, `DBRS_DB_PATH` and `DBRS_PROMPT` environment variables
+    pub fn from_args_and_env<I, S>(args: I) -> Result<Config, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        Config::from_args(args)?.with_env(|name| env::var(name).ok())
+    }
+
+    /// Fills the settings not given on the command line from the variables `var` looks up by name. Empty
+    /// variables count as unset. `DBRS_DB_PATH` is ignored for `--in-memory` tables.
+    pub fn with_env(mut self, var: impl Fn(&str) -> Option<String>) -> Result<Config, String> {
+        let var = |name| var(name).filter(|value| !value.is_empty());
+        if self.page_size.is_none() {
+            self.page_size = var(PAGE_SIZE_VAR).map(|value| parse_size(PAGE_SIZE_VAR, &value)).transpose()?;
+        }
+        if self.cache_size.is_none() {
+            self.cache_size = var(CACHE_SIZE_VAR).map(|value| parse_size(CACHE_SIZE_VAR, &value)).transpose()?;
+        }
+        if self.path.is_none() && !self.in_memory {
+            self.path = var(DB_PATH_VAR).map(PathBuf::from);
+        }
+        if self.prompt.is_none() {
+            self.prompt = var(PROMPT_VAR);
+        }
+        Ok(self)
+    }
+}
+
+fn parse_size(flag: &str, value: &str) -> Result<usize, String> {
+    match value.parse::<usize>() {
+        Ok(size) if size > 0 => Ok(size),
+        _ => Err(format!("Invalid value '{}' for {}, expected a positive number", value, flag)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::config::Config;
+
+    #[test]
+    fn parses_flags_and_path() -> Result<(), String> {
+        assert_eq!(Config::from_args(Vec::<String>::new())?, Config::default());
+
+        let config = Config::from_args(["--page-size", "8192", "test.db", "--cache-size=10", "--strict", "-f", "seed.dbrs"])?;
+        assert_eq!(config, Config {
+            path: Some(PathBuf::from("test.db")),
+            in_memory: false,
+            page_size: Some(8192),
+            cache_size: Some(10),
+            strict: true,
+            script: Some(PathBuf::from("seed.dbrs")),
+            recover: false,
+            read_only: false,
+            prompt: None,
+            quiet: false,
+        });
+        assert!(Config::from_args(["--in-memory"])?.in_memory);
+        assert!(Config::from_args(["--recover", "test.db"])?.recover);
+        assert!(Config::from_args(["--readonly", "test.db"])?.read_only);
+        assert_eq!(Config::from_args(["--prompt", "> "])?.prompt.as_deref(), Some("> "));
+        assert!(Config::from_args(["--quiet"])?.quiet);
+        assert!(Config::from_args(["--no-prompt"])?.quiet);
+
+        Ok(())
+    }
+
+    #[test]
+    fn env_provides_defaults() -> Result<(), String> {
+        let vars = |name: &str| match name {
+            "DBRS_PAGE_SIZE" => Some(String::from("8192")),
+            "DBRS_CACHE_SIZE" => Some(String::new()),
+            "DBRS_DB_PATH" => Some(String::from("env.db")),
+            "DBRS_PROMPT" => Some(String::from("env> ")),
+            _ => None,
+        };
+        let config = Config::from_args(["--page-size=1024"])?.with_env(vars)?;
+        assert_eq!(config.page_size, Some(1024));
+        assert_eq!(config.cache_size, None);
+        assert_eq!(config.path, Some(PathBuf::from("env.db")));
+        assert_eq!(config.prompt.as_deref(), Some("env> "));
+        assert_eq!(Config::from_args(["--in-memory"])?.with_env(vars)?.path, None);
+
+        let err = Config::default().with_env(|_| Some(String::from("lots"))).unwrap_err();
+        assert_eq!(err, "Invalid value 'lots' for DBRS_PAGE_SIZE, expected a positive number");
+
+        let cache_size = |name: &str| (name == "DBRS_CACHE_SIZE").then(|| String::from("12"));
+        assert_eq!(Config::from_args(["--cache-size", "3"])?.with_env(cache_size)?.cache_size, Some(3));
+        assert_eq!(Config::default().with_env(cache_size)?.cache_size, Some(12));
+
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        let err = Config::from_args(["test.db", "--in-memory"]).unwrap_err();
+        assert_eq!(err, "--in-memory cannot be combined with a database file");
+        assert!(Config::from_args(["--page-size"]).is_err());
+        assert!(Config::from_args(["-f"]).is_err());
+        assert!(Config::from_args(["--page-size", "big"]).is_err());
+        assert!(Config::from_args(["--cache-size=0"]).is_err());
+        assert!(Config::from_args(["--frobnicate"]).is_err());
+        assert!(Config::from_args(["a.db", "b.db"]).is_err());
+    }
+}
@@ -0,0 +1,297 @@
+use std::io::{self, Read, Write};
+use std::mem::MaybeUninit;
+
+use crate::completion::{common_prefix, Completer};
+use crate::history::History;
+
+const CTRL_C: u8 = 0x03;
+const CTRL_D: u8 = 0x04;
+const BACKSPACE: u8 = 0x08;
+const TAB: u8 = 0x09;
+const ESCAPE: u8 = 0x1b;
+const DELETE: u8 = 0x7f;
+
+/// A line editor for interactive sessions on a terminal. Supports moving the cursor with the left and right
+/// arrow keys, recalling earlier lines from the history with the up and down arrow keys and completing
+/// keywords with tab.
+///
+/// Every entered line is added to the history, including meta commands.
+#[derive(Debug)]
+pub struct Editor {
+    history: History,
+    completer: Completer,
+    /// Set once a failure to save the history has been reported
+    history_warned: bool,
+}
+
+impl Editor {
+    pub fn new(history: History) -> Self {
+        Editor {
+            history,
+            completer: Completer::default(),
+            history_warned: false,
+        }
+    }
+
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Shows the prompt and reads a line from stdin, which has to be a terminal. Returns `None` once the
+    /// user presses Ctrl-D on an empty line.
+    ///
+    /// Saving the line to the history file is best-effort: the line is still returned and kept in the
+    /// history in memory if the file cannot be written, and the first such failure is reported on stderr.
+    pub fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        let line = {
+            let _raw_mode = RawMode::enable()?;
+            self.edit(&mut io::stdin().lock(), &mut io::stdout().lock(), prompt)?
+        };
+        if let Some(line) = &line {
+            if let Err(error) = self.history.add(line) {
+                if !self.history_warned {
+                    eprintln!("Warning: could not save the history: {}", error);
+                    self.history_warned = true;
+                }
+            }
+        }
+        Ok(line)
+    }
+
+    fn edit(&self, input: &mut impl Read, output: &mut impl Write, prompt: &str) -> io::Result<Option<String>> {
+        let mut line = LineBuffer::default();
+        // index of the history entry being shown, equal to the number of entries for the line being typed
+        let mut history_idx = self.history.len();
+        let mut typed = String::new();
+
+        write!(output, "{}", prompt)?;
+        output.flush()?;
+        loop {
+            let byte = match read_byte(input)? {
+                Some(byte) => byte,
+                None => return Ok(None),
+            };
+            match byte {
+                b'\r' | b'\n' => {
+                    writeln!(output)?;
+                    return Ok(Some(line.to_string().trim().to_string()));
+                }
+                CTRL_D if line.chars.is_empty() => return Ok(None),
+                CTRL_C => {
+                    // discard the line and start over, like a shell
+                    write!(output, "^C\n{}", prompt)?;
+                    output.flush()?;
+                    line = LineBuffer::default();
+                    history_idx = self.history.len();
+                    continue;
+                }
+                BACKSPACE | DELETE => line.backspace(),
+                TAB => self.complete(&mut line, output, prompt)?,
+                ESCAPE => {
+                    // arrow keys arrive as `ESC [ <key>`, or `ESC O <key>` in application mode
+                    if !matches!(read_byte(input)?, Some(b'[') | Some(b'O')) {
+                        continue;
+                    }
+                    match read_byte(input)? {
+                        Some(b'A') if history_idx > 0 => {
+                            if history_idx == self.history.len() {
+                                typed = line.to_string();
+                            }
+                            history_idx -= 1;
+                            line = LineBuffer::from(self.history.entries()[history_idx].as_str());
+                        }
+                        Some(b'B') if history_idx < self.history.len() => {
+                            history_idx += 1;
+                            line = match self.history.entries().get(history_idx) {
+                                Some(entry) => LineBuffer::from(entry.as_str()),
+                                None => LineBuffer::from(typed.as_str()),
+                            };
+                        }
+                        Some(b'C') => line.cursor = (line.cursor + 1).min(line.chars.len()),
+                        Some(b'D') => line.cursor = line.cursor.saturating_sub(1),
+                        _ => continue,
+                    }
+                }
+                byte if byte >= 0x20 => {
+                    if let Some(c) = read_char(byte, input)? {
+                        line.insert(c);
+                    }
+                }
+                _ => continue,
+            }
+            line.redraw(output, prompt)?;
+        }
+    }
+
+    /// Completes the word before the cursor. A single candidate is inserted followed by a space, several
+    /// candidates are extended to their common prefix or listed if there is nothing to extend.
+    fn complete(&self, line: &mut LineBuffer, output: &mut impl Write, prompt: &str) -> io::Result<()> {
+        let before: String = line.chars[..line.cursor].iter().collect();
+        let candidates = self.completer.complete(&before);
+        let completion = match candidates.as_slice() {
+            [] => return Ok(()),
+            [candidate] => format!("{} ", candidate),
+            _ => common_prefix(&candidates).to_string(),
+        };
+
+        let word_len = before.trim_start().chars().count();
+        if completion.chars().count() > word_len {
+            completion.chars().skip(word_len).for_each(|c| line.insert(c));
+        } else {
+            writeln!(output)?;
+            writeln!(output, "{}", candidates.join("  "))?;
+            write!(output, "{}", prompt)?;
+        }
+        Ok(())
+    }
+}
+
+/// The line being edited and the position of the cursor in it, in chars
+#[derive(Debug, Default)]
+struct LineBuffer {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineBuffer {
+    fn insert(&mut self, c: char) {
+        self.chars.insert(self.cursor, c);
+        self.cursor += 1;
+    }
+
+    fn backspace(&mut self) {
+        if self.cursor > 0 {
+            self.cursor -= 1;
+            self.chars.remove(self.cursor);
+        }
+    }
+
+    /// Rewrites the current terminal line and moves the terminal cursor to the edit position
+    fn redraw(&self, output: &mut impl Write, prompt: &str) -> io::Result<()> {
+        write!(output, "\r{}{}\x1b[K", prompt, self)?;
+        let back = self.chars.len() - self.cursor;
+        if back > 0 {
+            write!(output, "\x1b[{}D", back)?;
+        }
+        output.flush()
+    }
+}
+
+impl From<&str> for LineBuffer {
+    fn from(s: &str) -> Self {
+        let chars: Vec<char> = s.chars().collect();
+        LineBuffer { cursor: chars.len(), chars }
+    }
+}
+
+impl std::fmt::Display for LineBuffer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.chars.iter().try_for_each(|c| write!(f, "{}", c))
+    }
+}
+
+fn read_byte(input: &mut impl Read) -> io::Result<Option<u8>> {
+    let mut buf = [0; 1];
+    match input.read(&mut buf)? {
+        0 => Ok(None),
+        _ => Ok(Some(buf[0])),
+    }
+}
+
+/// Reads the rest of the UTF-8 sequence starting with `first`, invalid sequences are dropped
+fn read_char(first: u8, input: &mut impl Read) -> io::Result<Option<char>> {
+    let len = match first {
+        0x00..=0x7f => 1,
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => return Ok(None),
+    };
+    let mut bytes = vec![first];
+    for _ in 1..len {
+        match read_byte(input)? {
+            Some(byte) => bytes.push(byte),
+            None => return Ok(None),
+        }
+    }
+    Ok(std::str::from_utf8(&bytes).ok().and_then(|s| s.chars().next()))
+}
+
+/// Switches the terminal on stdin to unbuffered input without echo, the previous settings are restored on drop
+struct RawMode {
+    original: libc::termios,
+}
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        let mut termios = MaybeUninit::<libc::termios>::uninit();
+        // SAFETY: tcgetattr fully initializes `termios` when it succeeds
+        let original = unsafe {
+            if libc::tcgetattr(libc::STDIN_FILENO, termios.as_mut_ptr()) != 0 {
+                return Err(io::Error::last_os_error());
+            }
+            termios.assume_init()
+        };
+
+        let mut raw = original;
+        // Ctrl-C is handled by the editor instead of killing the process, output processing stays on
+        raw.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG | libc::IEXTEN);
+        raw.c_cc[libc::VMIN] = 1;
+        raw.c_cc[libc::VTIME] = 0;
+        // SAFETY: `raw` is a valid termios obtained from tcgetattr
+        if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &raw) } != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(RawMode { original })
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        // SAFETY: `original` is the valid termios read in `enable`
+        unsafe {
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSADRAIN, &self.original);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::editor::Editor;
+    use crate::history::History;
+
+    fn edit(editor: &Editor, keys: &[u8]) -> Result<Option<String>, String> {
+        editor.edit(&mut &keys[..], &mut Vec::new(), "db> ").map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn edits_and_recalls_lines() -> Result<(), String> {
+        let mut history = History::in_memory();
+        history.add("insert 1 foo foo@bar.com").map_err(|e| e.to_string())?;
+        history.add(".stats").map_err(|e| e.to_string())?;
+        let editor = Editor::new(history);
+
+        assert_eq!(edit(&editor, b"selectt\x7f\n")?, Some(String::from("select")));
+        assert_eq!(edit(&editor, b"slect\x1b[D\x1b[D\x1b[D\x1b[De\r")?, Some(String::from("select")));
+        assert_eq!(edit(&editor, b"\x1b[A\n")?, Some(String::from(".stats")));
+        assert_eq!(edit(&editor, b"\x1b[A\x1b[A\x1b[A\n")?, Some(String::from("insert 1 foo foo@bar.com")));
+        // moving back down past the newest entry restores the line that was being typed
+        assert_eq!(edit(&editor, b"sel\x1b[A\x1b[Bect\n")?, Some(String::from("select")));
+        assert_eq!(edit(&editor, b"garbage\x03select\n")?, Some(String::from("select")));
+        assert_eq!(edit(&editor, "insert 1 \u{e9} e@f.g\n".as_bytes())?, Some(String::from("insert 1 \u{e9} e@f.g")));
+        assert_eq!(edit(&editor, b"\x04")?, None);
+        assert_eq!(edit(&editor, b"sel")?, None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn completes_on_tab() -> Result<(), String> {
+        let editor = Editor::new(History::in_memory());
+        assert_eq!(edit(&editor, b"sel\t1\n")?, Some(String::from("select 1")));
+        assert_eq!(edit(&editor, b".ex\t\n")?, Some(String::from(".exit")));
+        // ambiguous, the line stays as it is
+        assert_eq!(edit(&editor, b".\t\n")?, Some(String::from(".")));
+        Ok(())
+    }
+}
@@ -0,0 +1,70 @@
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+use crate::pager::{MAX_PAGE_SIZE, MIN_PAGE_SIZE};
+
+#[derive(Debug)]
+pub enum DbError {
+    CapacityExceeded,
+    InvalidRow(String),
+    InvalidId(u32),
+    DuplicateId(u32),
+    ReadOnly,
+    Locked,
+    BadMagic,
+    UnsupportedVersion(u8),
+    UnsupportedEncoding(u8),
+    RowSizeMismatch { expected: usize, found: usize },
+    PageSizeMismatch { expected: usize, found: usize },
+    InvalidPageSize(usize),
+    InvalidConfig(String),
+    Corruption { page: usize },
+    TruncatedFile { bytes: usize },
+    OutOfBounds { page: usize, offset: usize },
+    Io(io::Error),
+}
+
+impl fmt::Display for DbError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DbError::CapacityExceeded => write!(f, "Reached max number of pages"),
+            DbError::InvalidRow(e) => write!(f, "Invalid row: {}", e),
+            DbError::InvalidId(id) => write!(f, "Invalid id {}, the id is reserved", id),
+            DbError::DuplicateId(id) => write!(f, "Duplicate id {}", id),
+            DbError::ReadOnly => write!(f, "The table is read-only"),
+            DbError::Locked => write!(f, "The database file is locked by another connection"),
+            DbError::BadMagic => write!(f, "Not a dbrs database file"),
+            DbError::UnsupportedVersion(version) => write!(f, "Unsupported file format version {}", version),
+            DbError::UnsupportedEncoding(encoding) => write!(f, "Unsupported string encoding {}", encoding),
+            DbError::RowSizeMismatch { expected, found } => {
+                write!(f, "File stores rows of {} bytes but {} bytes are expected", found, expected)
+            }
+            DbError::PageSizeMismatch { expected, found } => {
+                write!(f, "File uses pages of {} bytes but {} bytes are expected", found, expected)
+            }
+            DbError::InvalidPageSize(page_size) => {
+                write!(f, "Invalid page size {}, pages must be {} to {} bytes", page_size, MIN_PAGE_SIZE, MAX_PAGE_SIZE)
+            }
+            DbError::InvalidConfig(e) => write!(f, "Invalid table configuration: {}", e),
+            DbError::Corruption { page } => write!(f, "Checksum mismatch, page {} is corrupt", page),
+            DbError::TruncatedFile { bytes } => write!(f, "File is truncated, {} bytes are missing at its end", bytes),
+            DbError::OutOfBounds { page, offset } => write!(f, "Row at byte offset {} overruns the end of page {}", offset, page),
+            DbError::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl Error for DbError {}
+
+impl From<io::Error> for DbError {
+    fn from(e: io::Error) -> Self {
+        DbError::Io(e)
+    }
+}
+
+impl From<DbError> for String {
+    fn from(e: DbError) -> Self {
+        e.to_string()
+    }
+}
@@ -0,0 +1,102 @@
+use crate::statement::Statement;
+use crate::table::Table;
+
+/// Describes how `statement` would be executed on `table` without executing it, e.g.
+/// `IndexLookup(id=5)` or `FullScan + filter(username like 'jo%')`
+pub fn explain(statement: &Statement, table: &Table) -> Result<String, String> {
+    let plan = match statement {
+        Statement::Insert(_) => append(table),
+        Statement::Upsert(row) => match table.positions_of_id(row.id).first() {
+            Some(position) => format!("IndexLookup(id={}) + Replace at position {}", row.id, position),
+            None => format!("IndexLookup(id={}) + {}", row.id, append(table)),
+        },
+        Statement::Select(args) => match args.trim() {
+            "" => String::from("IndexScan(id)"),
+            args if args.eq_ignore_ascii_case("physical") => String::from("FullScan"),
+            args if args.eq_ignore_ascii_case("first") => String::from("IndexLookup(min(id))"),
+            args if args.eq_ignore_ascii_case("last") => String::from("IndexLookup(max(id))"),
+            args => match args.parse::<usize>() {
+                Ok(position) => format!("PositionLookup({})", position),
+                Err(e) => return Err(format!("Error printing row for input '{}': {}", args, e)),
+            },
+        },
+        Statement::SelectColumns(columns) => {
+            let names: Vec<&str> = columns.iter().map(|&column| table.schema().name(column)).collect();
+            format!("IndexScan(id) + Project({})", names.join(", "))
+        }
+        Statement::SelectDistinct(column) => format!("IndexScan(id) + Distinct({})", table.schema().name(*column)),
+        Statement::CountGroupBy(column) => format!("FullScan + GroupBy({}) + count(*)", table.schema().name(*column)),
+        Statement::SelectWhere(condition) if condition.id_equals().is_some() => format!("IndexLookup({})", condition),
+        Statement::SelectWhere(condition)
+            if condition.username_equals().is_some_and(|username| table.positions_of_username(username).is_some()) => {
+            format!("IndexLookup({})", condition)
+        }
+        Statement::SelectWhere(condition) if condition.id_range().is_some() => format!("IndexRange({})", condition),
+        Statement::SelectWhere(condition) => format!("IndexScan(id) + filter({})", condition),
+        Statement::Aggregate(kind) => format!("FullScan + {}", kind),
+        Statement::Delete(condition) => format!("FullScan + filter({}) + Delete", condition),
+        Statement::CreateIndex(column) => format!("FullScan + CreateIndex({})", table.schema().name(*column)),
+        Statement::DropIndex(column) => format!("DropIndex({})", table.schema().name(*column)),
+        Statement::Explain(statement) => return explain(statement, table),
+    };
+    Ok(plan)
+}
+
+fn append(table: &Table) -> String {
+    let placement = table.next_placement();
+    let page = if placement.new_page { "new page" } else { "page" };
+    format!("Append at {} {} offset {}", page, placement.page_num, placement.byte_offset)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::explain::explain;
+    use crate::row::Row;
+    use crate::statement::parse_statement;
+    use crate::table::Table;
+
+    fn plan(s: &str, table: &Table) -> Result<String, String> {
+        explain(&parse_statement(s)?, table)
+    }
+
+    #[test]
+    fn lookups() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::from_string("5 john john@example.com")?)?;
+
+        assert_eq!(plan("select where id = 5", &table)?, "IndexLookup(id=5)");
+        assert_eq!(plan("select where username like 'jo%'", &table)?, "IndexScan(id) + filter(username like 'jo%')");
+        assert_eq!(plan("explain select where id = 5", &table)?, "IndexLookup(id=5)");
+        assert_eq!(plan("select max(id)", &table)?, "FullScan + max(id)");
+        assert_eq!(plan("select where id >= 3", &table)?, "IndexRange(id>=3)");
+        assert_eq!(plan("select where id != 3", &table)?, "IndexScan(id) + filter(id!=3)");
+        assert_eq!(plan("select where id > 3 and id < 9", &table)?, "IndexRange(id>3 and id<9)");
+        assert_eq!(plan("select email, id", &table)?, "IndexScan(id) + Project(email, id)");
+        assert_eq!(plan("select username, count(*) group by username", &table)?, "FullScan + GroupBy(username) + count(*)");
+
+        assert_eq!(plan("select where username = 'john'", &table)?, "IndexScan(id) + filter(username = 'john')");
+        table.create_username_index()?;
+        assert_eq!(plan("select where username = 'john'", &table)?, "IndexLookup(username = 'john')");
+        assert_eq!(plan("select where username like 'john'", &table)?, "IndexLookup(username like 'john')");
+        assert_eq!(plan("select where username like 'jo%'", &table)?, "IndexScan(id) + filter(username like 'jo%')");
+
+        Ok(())
+    }
+
+    #[test]
+    fn writes() -> Result<(), String> {
+        let mut table = Table::new();
+        assert_eq!(plan("insert 5 john john@example.com", &table)?, "Append at new page 0 offset 0");
+        table.insert_row(&Row::from_string("5 john john@example.com")?)?;
+
+        assert_eq!(plan("insert 6 mary mary@example.com", &table)?, "Append at page 0 offset 291");
+        assert_eq!(plan("insert or replace 5 jo jo@example.com", &table)?, "IndexLookup(id=5) + Replace at position 0");
+        assert_eq!(plan("delete where id = 5", &table)?, "FullScan + filter(id=5) + Delete");
+        assert_eq!(plan("create index on username", &table)?, "FullScan + CreateIndex(username)");
+        assert_eq!(plan("drop index on username", &table)?, "DropIndex(username)");
+        // explaining changes nothing
+        assert_eq!(table.num_rows(), 1);
+
+        Ok(())
+    }
+}
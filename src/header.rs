@@ -0,0 +1,178 @@
+use crate::error::DbError;
+use crate::pager::{check_page_size, PAGE_SIZE};
+use crate::row::{StringEncoding, ROW_SIZE};
+
+pub const MAGIC: &[u8; 4] = b"DBRS";
+/// Version 2 added the page size to the header, version 3 a checksum to every page, version 4 the
+/// string encoding to the header
+pub const FORMAT_VERSION: u8 = 4;
+pub const HEADER_SIZE: usize = 32;
+
+/// Version 1 headers have no page size field, their pages are always this big
+pub const V1_PAGE_SIZE: usize = 4096;
+
+const VERSION_OFFSET: usize = MAGIC.len();
+const ROW_SIZE_OFFSET: usize = 8;
+const NUM_ROWS_OFFSET: usize = ROW_SIZE_OFFSET + 4;
+const PAGE_SIZE_OFFSET: usize = NUM_ROWS_OFFSET + 8;
+const ENCODING_OFFSET: usize = PAGE_SIZE_OFFSET + 4;
+
+/// The fixed-size region at the start of a database file, followed by the pages.
+///
+/// Layout: magic bytes, format version, 3 padding bytes, row size as u32, number of rows as u64 and,
+/// since version 2, page size as u32, all little endian. Since version 4 a byte for the string encoding
+/// follows, 0 for NUL-terminated and 1 for length-prefixed strings. The remaining bytes up to `HEADER_SIZE` are
+/// reserved and zeroed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Header {
+    pub version: u8,
+    pub row_size: u32,
+    pub num_rows: u64,
+    pub page_size: u32,
+    pub encoding: StringEncoding,
+}
+
+impl Header {
+    pub fn new(num_rows: usize) -> Self {
+        Header {
+            version: FORMAT_VERSION,
+            row_size: ROW_SIZE as u32,
+            num_rows: num_rows as u64,
+            page_size: PAGE_SIZE as u32,
+            encoding: StringEncoding::NulTerminated,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; HEADER_SIZE] {
+        let mut bytes = [0; HEADER_SIZE];
+        bytes[..MAGIC.len()].copy_from_slice(MAGIC);
+        bytes[VERSION_OFFSET] = self.version;
+        bytes[ROW_SIZE_OFFSET..NUM_ROWS_OFFSET].copy_from_slice(&self.row_size.to_le_bytes());
+        bytes[NUM_ROWS_OFFSET..PAGE_SIZE_OFFSET].copy_from_slice(&self.num_rows.to_le_bytes());
+        if self.version >= 2 {
+            bytes[PAGE_SIZE_OFFSET..PAGE_SIZE_OFFSET + 4].copy_from_slice(&self.page_size.to_le_bytes());
+        }
+        if self.version >= 4 {
+            bytes[ENCODING_OFFSET] = match self.encoding {
+                StringEncoding::NulTerminated => 0,
+                StringEncoding::LengthPrefixed => 1,
+            };
+        }
+        bytes
+    }
+
+    /// Reads the header at the start of `bytes`, rejecting foreign files and versions newer than
+    /// `FORMAT_VERSION`. Headers of older versions are returned as is, see `migrate`.
+    pub fn decode(bytes: &[u8]) -> Result<Self, DbError> {
+        if bytes.len() < HEADER_SIZE || &bytes[..MAGIC.len()] != MAGIC {
+            return Err(DbError::BadMagic);
+        }
+
+        let version = bytes[VERSION_OFFSET];
+        if version == 0 || version > FORMAT_VERSION {
+            return Err(DbError::UnsupportedVersion(version));
+        }
+
+        let mut row_size_bytes = [0; 4];
+        row_size_bytes.copy_from_slice(&bytes[ROW_SIZE_OFFSET..NUM_ROWS_OFFSET]);
+        let row_size = u32::from_le_bytes(row_size_bytes);
+
+        let mut num_rows_bytes = [0; 8];
+        num_rows_bytes.copy_from_slice(&bytes[NUM_ROWS_OFFSET..PAGE_SIZE_OFFSET]);
+        let num_rows = u64::from_le_bytes(num_rows_bytes);
+
+        let page_size = if version >= 2 {
+            let mut page_size_bytes = [0; 4];
+            page_size_bytes.copy_from_slice(&bytes[PAGE_SIZE_OFFSET..PAGE_SIZE_OFFSET + 4]);
+            u32::from_le_bytes(page_size_bytes)
+        } else {
+            V1_PAGE_SIZE as u32
+        };
+
+        // older versions only know NUL-terminated strings
+        let encoding = match bytes[ENCODING_OFFSET] {
+            _ if version < 4 => StringEncoding::NulTerminated,
+            0 => StringEncoding::NulTerminated,
+            1 => StringEncoding::LengthPrefixed,
+            encoding => return Err(DbError::UnsupportedEncoding(encoding)),
+        };
+
+        Ok(Header { version, row_size, num_rows, page_size, encoding })
+    }
+
+    /// Checks that pages and rows of the file can be read with the current layout
+    pub fn validate(&self) -> Result<(), DbError> {
+        if self.row_size as usize != ROW_SIZE {
+            return Err(DbError::RowSizeMismatch { expected: ROW_SIZE, found: self.row_size as usize });
+        }
+        check_page_size(self.page_size as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::DbError;
+    use crate::header::{Header, FORMAT_VERSION, HEADER_SIZE, V1_PAGE_SIZE};
+    use crate::row::StringEncoding;
+
+    #[test]
+    fn round_trip() -> Result<(), String> {
+        let header = Header::new(1234);
+        let bytes = header.encode();
+        assert_eq!(&bytes[..4], b"DBRS");
+        assert_eq!(Header::decode(&bytes)?, header);
+
+        let header = Header { encoding: StringEncoding::LengthPrefixed, ..Header::new(1) };
+        assert_eq!(Header::decode(&header.encode())?, header);
+
+        Ok(())
+    }
+
+    #[test]
+    fn unknown_encoding() {
+        let mut bytes = Header::new(1).encode();
+        bytes[24] = 7;
+        assert!(matches!(Header::decode(&bytes), Err(DbError::UnsupportedEncoding(7))));
+    }
+
+    #[test]
+    fn bad_magic() {
+        let mut bytes = Header::new(1).encode();
+        bytes[0] = b'X';
+        assert!(matches!(Header::decode(&bytes), Err(DbError::BadMagic)));
+        assert!(matches!(Header::decode(b"DBRS"), Err(DbError::BadMagic)));
+    }
+
+    #[test]
+    fn unknown_version() {
+        let mut bytes = Header::new(1).encode();
+        bytes[4] = FORMAT_VERSION + 1;
+        assert!(matches!(Header::decode(&bytes), Err(DbError::UnsupportedVersion(v)) if v == FORMAT_VERSION + 1));
+    }
+
+    #[test]
+    fn row_size_mismatch() -> Result<(), String> {
+        let mut bytes = Header::new(1).encode();
+        bytes[8] = bytes[8].wrapping_add(1);
+        let header = Header::decode(&bytes[..HEADER_SIZE])?;
+        assert!(matches!(header.validate(), Err(DbError::RowSizeMismatch { .. })));
+
+        Ok(())
+    }
+
+    #[test]
+    fn v1_has_no_page_size() -> Result<(), String> {
+        let mut header = Header::new(3);
+        header.version = 1;
+        header.page_size = 1;
+        let bytes = header.encode();
+        assert!(bytes[20..].iter().all(|&b| b == 0));
+
+        let decoded = Header::decode(&bytes)?;
+        assert_eq!(decoded.version, 1);
+        assert_eq!(decoded.num_rows, 3);
+        assert_eq!(decoded.page_size as usize, V1_PAGE_SIZE);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,120 @@
+use std::env;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+pub const HISTORY_FILE: &str = ".dbrs_history";
+/// Number of entries kept in memory, older entries stay in the file but are not loaded
+pub const MAX_ENTRIES: usize = 1000;
+
+/// Lines entered in the REPL, oldest first. A file-backed history appends every new entry to its file.
+#[derive(Debug)]
+pub struct History {
+    path: Option<PathBuf>,
+    entries: Vec<String>,
+}
+
+impl History {
+    pub fn in_memory() -> Self {
+        History {
+            path: None,
+            entries: Vec::new(),
+        }
+    }
+
+    /// `~/.dbrs_history`, or `None` if the home directory is unknown
+    pub fn default_path() -> Option<PathBuf> {
+        env::var_os("HOME").map(|home| Path::new(&home).join(HISTORY_FILE))
+    }
+
+    /// Loads the history stored at `path`, a missing file is an empty history
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+        let mut entries: Vec<String> = contents.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(String::from)
+            .collect();
+        entries.drain(..entries.len().saturating_sub(MAX_ENTRIES));
+
+        Ok(History {
+            path: Some(path.to_path_buf()),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Records a line, skipping blank lines and repeats of the previous entry
+    pub fn add(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim();
+        if line.is_empty() || self.entries.last().map(String::as_str) == Some(line) {
+            return Ok(());
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.remove(0);
+        }
+        self.entries.push(line.to_string());
+
+        if let Some(path) = &self.path {
+            let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+            writeln!(file, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::history::History;
+
+    #[test]
+    fn add_skips_blanks_and_repeats() -> Result<(), String> {
+        let mut history = History::in_memory();
+        for line in ["select", "select", "  ", "insert 1 a a@b.c", ".stats", "select"] {
+            history.add(line).map_err(|e| e.to_string())?;
+        }
+        assert_eq!(history.entries(), ["select", "insert 1 a a@b.c", ".stats", "select"]);
+        Ok(())
+    }
+
+    #[test]
+    fn persists_entries() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-history-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut history = History::open(&path).map_err(|e| e.to_string())?;
+        assert!(history.is_empty());
+        history.add("insert 1 a a@b.c").map_err(|e| e.to_string())?;
+        history.add(".exit").map_err(|e| e.to_string())?;
+
+        let history = History::open(&path).map_err(|e| e.to_string())?;
+        assert_eq!(history.entries(), ["insert 1 a a@b.c", ".exit"]);
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn keeps_entries_it_cannot_save() -> Result<(), String> {
+        // the file cannot be created in a missing directory
+        let path = std::env::temp_dir().join(format!("dbrs-missing-{}", std::process::id())).join("history");
+        let mut history = History::open(&path).map_err(|e| e.to_string())?;
+        assert!(history.add("select").is_err());
+        assert_eq!(history.entries(), ["select"]);
+        Ok(())
+    }
+}
@@ -0,0 +1,51 @@
+/// Enters a span of the `trace` module that lasts until the end of the enclosing block, e.g.
+/// `trace_span!("insert_row", id = row.id)`. Expands to nothing without the `trace` feature.
+#[cfg(feature = "trace")]
+macro_rules! trace_span {
+    ($name:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        let _span = $crate::trace::enter_span($name, &[$((stringify!($key), &$value as &dyn std::fmt::Display)),*]);
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_span {
+    ($($arg:tt)*) => {};
+}
+
+/// Reports an event of the `trace` module, e.g. `trace_event!("load_pages", num_pages = 3)`. Expands to
+/// nothing without the `trace` feature.
+#[cfg(feature = "trace")]
+macro_rules! trace_event {
+    ($name:literal $(, $key:ident = $value:expr)* $(,)?) => {
+        $crate::trace::event($name, &[$((stringify!($key), &$value as &dyn std::fmt::Display)),*])
+    };
+}
+
+#[cfg(not(feature = "trace"))]
+macro_rules! trace_event {
+    ($($arg:tt)*) => {};
+}
+
+pub mod completion;
+pub mod concurrency;
+pub mod config;
+#[cfg(feature = "line-editor")]
+pub mod editor;
+pub mod error;
+pub mod explain;
+pub mod header;
+pub mod history;
+pub mod migrate;
+#[cfg(feature = "async")]
+pub mod nonblocking;
+pub mod output;
+pub mod pager;
+pub mod repl;
+pub mod row;
+pub mod schema;
+pub mod statement;
+pub mod table;
+pub mod token;
+#[cfg(feature = "trace")]
+pub mod trace;
+pub mod util;
@@ -1,29 +1,123 @@
-use std::io::{self, BufRead, Write};
+use std::env;
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
 use std::process;
 
-use crate::row::Row;
-use crate::table::Table;
-
-pub mod row;
-pub mod table;
-
-const INSERT_CMD: &str = "insert";
-const SELECT_CMD: &str = "select";
-
-#[derive(Debug)]
-enum Statement {
-    Insert(Row),
-    Select(String),
-}
+#[cfg(feature = "line-editor")]
+use dbrs::editor::Editor;
+use dbrs::config::Config;
+#[cfg(feature = "line-editor")]
+use dbrs::history::History;
+use dbrs::repl::{handle_input, read_line, MetaResult, Repl, DEFAULT_PROMPT};
+use dbrs::table::Table;
+
+const HELP_FLAG: &str = "--help";
+const VERSION_FLAG: &str = "--version";
+const USAGE: &str = "\
+Usage: dbrs [OPTIONS] [FILE]
+
+Reads statements and meta commands from stdin, run .help in the REPL for a list of meta commands.
+The table is stored in FILE, which is created if it does not exist, or kept in memory without one.
+
+Options:
+  --in-memory          Keep the table in memory, cannot be combined with FILE
+  --page-size <BYTES>  Page size of a new FILE, an existing FILE must use the same size
+  --cache-size <PAGES> Number of pages kept in memory, which limits the size of the table
+  --strict             Exit with a nonzero code at the first failing statement
+  --readonly           Open FILE for reading only, every change fails
+  --recover            Open a truncated FILE by dropping its incomplete last page and the rows in it
+  --prompt <TEXT>      Show TEXT as the prompt before every line, .prompt changes it later
+  --quiet, --no-prompt Print only query results and errors, without prompts or confirmations of
+                       inserts and deletes
+  -f <SCRIPT>          Run the lines of SCRIPT instead of reading stdin, exits with a nonzero code if
+                       any line failed
+  --help               Show this message and exit
+  --version            Show the version and exit
+
+Environment:
+  DBRS_DB_PATH         FILE to open if none is given
+  DBRS_PAGE_SIZE       Page size if --page-size is not given
+  DBRS_CACHE_SIZE      Number of pages kept in memory if --cache-size is not given
+  DBRS_PROMPT          Prompt if --prompt is not given
+";
 
 fn main() {
-    let mut table = Table::new();
+    let args: Vec<String> = env::args().skip(1).collect();
+    if args.iter().any(|arg| arg == HELP_FLAG) {
+        print!("{}", USAGE);
+        return;
+    }
+    if args.iter().any(|arg| arg == VERSION_FLAG) {
+        println!("dbrs {}", env!("CARGO_PKG_VERSION"));
+        return;
+    }
+    let config = Config::from_args_and_env(&args).unwrap_or_else(|error| {
+        eprintln!("{}\n\n{}", error, USAGE);
+        process::exit(1);
+    });
+    let table = Table::with_config(&config).unwrap_or_else(|error| {
+        eprintln!("{}", error);
+        process::exit(1);
+    });
+    if table.dropped_bytes() > 0 {
+        eprintln!("Warning: dropped {} bytes of an incomplete last page, the table holds {} rows", table.dropped_bytes(), table.num_rows());
+    }
+    let mut repl = Repl::new(table)
+        .with_interactive(config.script.is_none() && io::stdin().is_terminal())
+        .with_prompt(config.prompt.as_deref().unwrap_or(DEFAULT_PROMPT))
+        .with_quiet(config.quiet);
+    let mut stdout = io::stdout();
+
+    let mut input = match &config.script {
+        Some(path) => Input::script(path).unwrap_or_else(|error| {
+            eprintln!("{}", error);
+            process::exit(1);
+        }),
+        None => Input::detect(),
+    };
+    let mut failed = false;
 
     loop {
-        print_prompt();
-
-        match read_line() {
-            Ok(input) => handle_input(input, &mut table),
+        match input.read_line(repl.prompt()) {
+            Ok(Some(line)) => {
+                match handle_input(&line, &mut repl, &mut stdout) {
+                    Ok(MetaResult::Continue) => {}
+                    Ok(MetaResult::Exit(code)) => {
+                        let _ = stdout.flush();
+                        // a script that ends in .exit still reports its failed lines
+                        if failed && code == 0 && input.line_num().is_some() {
+                            process::exit(1);
+                        }
+                        process::exit(code);
+                    }
+                    Err(error) => {
+                        match input.line_num() {
+                            Some(line_num) => eprintln!("Line {}: {}", line_num, error),
+                            None => eprintln!("{}", error),
+                        }
+                        failed = true;
+                        // in strict mode the first failing statement terminates the process with a nonzero exit code
+                        if config.strict {
+                            process::exit(1);
+                        }
+                    }
+                }
+            }
+            Ok(None) => {
+                // stdin was closed, e.g. at the end of a piped script
+                if input.line_num().is_none() && !repl.is_quiet() {
+                    println!();
+                }
+                if let Err(error) = repl.table.flush() {
+                    eprintln!("{}", error);
+                    process::exit(1);
+                }
+                if failed && input.line_num().is_some() {
+                    process::exit(1);
+                }
+                break;
+            }
             Err(error) => {
                 eprintln!("Error reading input: {:?}. Please try again.", error);
             }
@@ -31,91 +125,60 @@ fn main() {
     }
 }
 
-fn handle_input(input: String, table: &mut Table) {
-    if input.starts_with(".") {
-        do_meta_command(input.as_str())
-    } else {
-        match parse_statement(input.as_str()) {
-            Ok(statement) => do_process_statement(statement, table),
-            Err(error) => eprintln!("Error: {}", error),
-        }
-    }
+/// Where the REPL reads its lines from
+enum Input {
+    /// Lines read as they are, e.g. from a piped script
+    Plain,
+    /// A terminal with line editing and a history persisted to `~/.dbrs_history`
+    #[cfg(feature = "line-editor")]
+    Editor(Editor),
+    /// The lines of a script file given with `-f`, without prompts
+    Script { lines: Vec<String>, line_num: usize },
 }
 
-fn do_meta_command(command: &str) {
-    match command {
-        ".exit" => {
-            println!("Exiting...");
-            process::exit(0)
+impl Input {
+    fn detect() -> Self {
+        #[cfg(feature = "line-editor")]
+        if io::stdin().is_terminal() {
+            let history = History::default_path()
+                .and_then(|path| History::open(&path).ok())
+                .unwrap_or_else(History::in_memory);
+            return Input::Editor(Editor::new(history));
         }
-        _ => {
-            println!("Unknown command: {}", command)
+        Input::Plain
+    }
+
+    fn script(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path).map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+        Ok(Input::Script { lines: contents.lines().map(|line| line.trim().to_string()).collect(), line_num: 0 })
+    }
+
+    /// The number of the line last read from a script
+    fn line_num(&self) -> Option<usize> {
+        match self {
+            Input::Script { line_num, .. } => Some(*line_num),
+            _ => None,
         }
     }
-}
 
-fn do_process_statement(statement: Statement, table: &mut Table) {
-    match statement {
-        Statement::Insert(row) => {
-            match table.insert_row(&row) {
-                Ok(_) => println!("Row inserted successfully"),
-                Err(cause) => println!("Error inserting row: {}", cause)
+    fn read_line(&mut self, prompt: &str) -> io::Result<Option<String>> {
+        match self {
+            Input::Plain => {
+                print_prompt(prompt);
+                read_line()
             }
-        }
-        Statement::Select(args) => {
-            if args.trim().is_empty() {
-                for i in 0..table.num_rows() {
-                    let row = table.select_row(i).unwrap();
-                    println!("{:?}", row);
-                }
-            } else {
-                match args.trim().parse::<usize>() {
-                    Ok(row_idx) => print_table_row(&table, row_idx),
-                    Err(err) => eprintln!("Error printing row for input '{}': {}", args, err)
-                }
+            #[cfg(feature = "line-editor")]
+            Input::Editor(editor) => editor.read_line(prompt),
+            Input::Script { lines, line_num } => {
+                let line = lines.get(*line_num).cloned();
+                *line_num += 1;
+                Ok(line)
             }
         }
     }
 }
 
-fn print_table_row(table: &Table, row_idx: usize) {
-    let num_rows = table.num_rows();
-
-    if num_rows == 0 {
-        println!("Table is empty, nothing to print for index {}", row_idx);
-    } else if row_idx >= table.num_rows() {
-        println!("Row index out of bounds: {} is not in [0, {}]", row_idx, num_rows)
-    } else {
-        println!("{:?}", table.select_row(row_idx).unwrap())
-    }
-}
-
-
-fn print_prompt() {
-    print!("db> ");
+fn print_prompt(prompt: &str) {
+    print!("{}", prompt);
     let _ = io::stdout().flush();
 }
-
-fn read_line() -> io::Result<String> {
-    let stdin = io::stdin();
-    let mut handle = stdin.lock();
-    let mut input = String::new();
-    handle.read_line(&mut input)?;
-    Ok(input.trim().to_string())
-}
-
-fn parse_statement(s: &str) -> Result<Statement, String> {
-    match s.trim().to_lowercase().split_ascii_whitespace().next().unwrap_or("") {
-        INSERT_CMD => {
-            match Row::from_string(s[INSERT_CMD.len()..].trim()) {
-                Ok(row) => Ok(Statement::Insert(row)),
-                Err(e) => Err(format!("Illegal insert statement: {}", e))
-            }
-        }
-        SELECT_CMD => {
-            let args = String::from(s[SELECT_CMD.len()..].trim());
-            Ok(Statement::Select(args))
-        }
-        _ => Err("Unknown statement".to_string()),
-    }
-}
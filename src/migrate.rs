@@ -0,0 +1,173 @@
+use crate::error::DbError;
+use crate::header::{Header, FORMAT_VERSION, V1_PAGE_SIZE};
+use crate::pager::{check_page_size, PAGE_SIZE};
+use crate::row::{Row, StringEncoding, ROW_SIZE};
+use crate::table::ROWS_PER_PAGE;
+
+/// Upgrades the page data of a file written with an older format version to `FORMAT_VERSION`.
+///
+/// Migrations run one version at a time and rewrite the rows whose layout changed. Newer versions are already rejected by
+/// `Header::decode`, there is no way to migrate a file downward.
+pub fn migrate(mut header: Header, mut data: Vec<u8>) -> Result<(Header, Vec<u8>), DbError> {
+    while header.version < FORMAT_VERSION {
+        (header, data) = match header.version {
+            1 => migrate_v1_to_v2(&header, &data)?,
+            2 => migrate_v2_to_v3(&header, &data)?,
+            3 => migrate_v3_to_v4(&header, data)?,
+            version => return Err(DbError::UnsupportedVersion(version)),
+        };
+    }
+    Ok((header, data))
+}
+
+/// Version 2 records the page size in the header. Version 1 pages are always `V1_PAGE_SIZE` bytes,
+/// their rows are copied one by one into pages of `PAGE_SIZE` bytes.
+pub fn migrate_v1_to_v2(header: &Header, data: &[u8]) -> Result<(Header, Vec<u8>), DbError> {
+    if header.version != 1 {
+        return Err(DbError::UnsupportedVersion(header.version));
+    }
+    let rows = read_rows(header, data, V1_PAGE_SIZE, V1_PAGE_SIZE / ROW_SIZE)?;
+    let migrated = write_rows(&rows, PAGE_SIZE, PAGE_SIZE / ROW_SIZE);
+
+    let header = Header { version: 2, ..Header::new(rows.len()) };
+    Ok((header, migrated))
+}
+
+/// Version 3 reserves the end of each page for a checksum, which may leave room for fewer rows per
+/// page. The rows are copied one by one, the checksums are computed when the pages are written.
+pub fn migrate_v2_to_v3(header: &Header, data: &[u8]) -> Result<(Header, Vec<u8>), DbError> {
+    if header.version != 2 {
+        return Err(DbError::UnsupportedVersion(header.version));
+    }
+    let page_size = header.page_size as usize;
+    // the header is only validated after the migration, a corrupt page size must not reach `row_offset`
+    check_page_size(page_size)?;
+    let rows = read_rows(header, data, page_size, page_size / ROW_SIZE)?;
+    let migrated = write_rows(&rows, PAGE_SIZE, ROWS_PER_PAGE);
+
+    let header = Header { version: 3, ..Header::new(rows.len()) };
+    Ok((header, migrated))
+}
+
+/// Version 4 records the string encoding in the header. Older files always use NUL-terminated strings,
+/// which stays the encoding of the migrated file, so the pages are kept as they are.
+pub fn migrate_v3_to_v4(header: &Header, data: Vec<u8>) -> Result<(Header, Vec<u8>), DbError> {
+    if header.version != 3 {
+        return Err(DbError::UnsupportedVersion(header.version));
+    }
+    let header = Header { version: 4, encoding: StringEncoding::NulTerminated, ..*header };
+    Ok((header, data))
+}
+
+fn read_rows(header: &Header, data: &[u8], page_size: usize, rows_per_page: usize) -> Result<Vec<Row>, DbError> {
+    if header.row_size as usize != ROW_SIZE {
+        return Err(DbError::RowSizeMismatch { expected: ROW_SIZE, found: header.row_size as usize });
+    }
+
+    (0..header.num_rows as usize)
+        .map(|position| {
+            let offset = row_offset(position, page_size, rows_per_page);
+            let bytes = data.get(offset..offset + ROW_SIZE)
+                .ok_or_else(|| DbError::InvalidRow(format!("Row {} is missing from the version {} file", position, header.version)))?;
+            Row::deserialize(bytes).map_err(DbError::InvalidRow)
+        })
+        .collect()
+}
+
+fn write_rows(rows: &[Row], page_size: usize, rows_per_page: usize) -> Vec<u8> {
+    let mut data = Vec::new();
+    for (position, row) in rows.iter().enumerate() {
+        let offset = row_offset(position, page_size, rows_per_page);
+        data.resize(offset + ROW_SIZE, 0);
+        data[offset..offset + ROW_SIZE].copy_from_slice(&row.serialize());
+    }
+    // the last page is written out in full
+    data.resize(data.len().next_multiple_of(page_size), 0);
+    data
+}
+
+/// Byte offset of a row in data made of pages of `page_size` bytes holding `rows_per_page` rows each
+fn row_offset(position: usize, page_size: usize, rows_per_page: usize) -> usize {
+    position / rows_per_page * page_size + position % rows_per_page * ROW_SIZE
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::DbError;
+    use crate::header::{Header, FORMAT_VERSION, V1_PAGE_SIZE};
+    use crate::migrate::write_rows;
+    use crate::pager::PAGE_SIZE;
+    use crate::row::{Row, ROW_SIZE};
+    use crate::table::Table;
+    use crate::util::user_row;
+
+    /// Writes the rows in the version 1 layout
+    fn v1_file(rows: &[Row]) -> Vec<u8> {
+        let header = Header { version: 1, ..Header::new(rows.len()) };
+        let mut bytes = header.encode().to_vec();
+        bytes.extend(write_rows(rows, V1_PAGE_SIZE, V1_PAGE_SIZE / ROW_SIZE));
+        bytes
+    }
+
+    #[test]
+    fn open_v1_file() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-open-v1-file-{}.db", std::process::id()));
+        let rows: Vec<Row> = (0..30)
+            .map(user_row)
+            .collect();
+        std::fs::write(&path, v1_file(&rows)).map_err(|e| e.to_string())?;
+
+        let table = Table::open(&path)?;
+        assert_eq!(table.num_rows(), rows.len());
+        assert_eq!(table.iter().collect::<Vec<Row>>(), rows);
+        drop(table);
+
+        // the upgraded file was written back
+        let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+        let header = Header::decode(&bytes)?;
+        assert_eq!(header.version, FORMAT_VERSION);
+        assert_eq!(header.num_rows as usize, rows.len());
+
+        let table = Table::open(&path)?;
+        assert_eq!(table.iter().collect::<Vec<Row>>(), rows);
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn open_v2_file() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-open-v2-file-{}.db", std::process::id()));
+        let rows: Vec<Row> = (0..50)
+            .map(user_row)
+            .collect();
+        let header = Header { version: 2, ..Header::new(rows.len()) };
+        let mut bytes = header.encode().to_vec();
+        bytes.extend(write_rows(&rows, PAGE_SIZE, PAGE_SIZE / ROW_SIZE));
+        std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+
+        let table = Table::open(&path)?;
+        assert_eq!(table.iter().collect::<Vec<Row>>(), rows);
+        drop(table);
+
+        let table = Table::open(&path)?;
+        assert_eq!(table.iter().collect::<Vec<Row>>(), rows);
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn corrupt_v2_page_size() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-corrupt-v2-{}.db", std::process::id()));
+        let header = Header { version: 2, page_size: 100, ..Header::new(1) };
+        let mut bytes = header.encode().to_vec();
+        bytes.extend(write_rows(&[user_row(1)], PAGE_SIZE, PAGE_SIZE / ROW_SIZE));
+        std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+
+        assert!(matches!(Table::open(&path), Err(DbError::InvalidPageSize(100))));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Statements run off the caller's thread for async code, enabled by the `async` feature.
+//!
+//! The engine stays synchronous: `AsyncDatabase::execute` queues a statement on a worker thread and
+//! returns a future that completes with its result, so an async task awaiting it never blocks its
+//! executor. The future does not depend on a particular runtime.
+use std::any::Any;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, PoisonError};
+use std::task::{Context, Poll, Waker};
+use std::thread;
+
+use crate::concurrency::SharedTable;
+use crate::row::Row;
+use crate::statement::{parse_statement_with, Statement};
+use crate::table::{Table, Upsert};
+
+/// The outcome of a statement run by `AsyncDatabase::execute`
+#[derive(Debug, PartialEq)]
+pub enum QueryResult {
+    /// An inserted row and its position
+    Inserted(usize),
+    /// A row replaced by `insert or replace` and its position
+    Replaced(usize),
+    /// The rows of a select ordered by id
+    Rows(Vec<Row>),
+    /// The number of rows a delete removed
+    Deleted(usize),
+}
+
+/// A job queued on the worker thread of an `AsyncDatabase`
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A table that async code runs statements on, it can be cloned to share the table between tasks.
+///
+/// Each database created by `new` starts one worker thread, which its clones share and which exits once
+/// the last clone is dropped. Statements run on it one after another in the order they were executed,
+/// so a slow statement delays the ones queued behind it.
+#[derive(Debug, Clone)]
+pub struct AsyncDatabase {
+    table: SharedTable,
+    jobs: Sender<Job>,
+}
+
+impl AsyncDatabase {
+    pub fn new(table: Table) -> Self {
+        let (jobs, queue) = mpsc::channel::<Job>();
+        thread::spawn(move || {
+            for job in queue {
+                job();
+            }
+        });
+        AsyncDatabase { table: SharedTable::new(table), jobs }
+    }
+
+    /// Queues a statement on the worker thread. Supports inserts, `insert or replace`, `select`,
+    /// `select where` and `delete where`, other statements fail.
+    pub fn execute(&self, statement: &str) -> Pending<Result<QueryResult, String>> {
+        let table = self.table.clone();
+        let statement = statement.to_string();
+        Pending::spawn(&self.jobs, move || run(&table, &statement))
+    }
+}
+
+fn run(table: &SharedTable, statement: &str) -> Result<QueryResult, String> {
+    let statement = table.read_with(|table| parse_statement_with(statement, table.schema()))?;
+    let result = match statement {
+        Statement::Insert(row) => QueryResult::Inserted(table.write_with(|table| table.insert_row(&row))?),
+        Statement::Upsert(row) => match table.write_with(|table| table.upsert_row(&row))? {
+            Upsert::Inserted(position) => QueryResult::Inserted(position),
+            Upsert::Replaced(position) => QueryResult::Replaced(position),
+        },
+        Statement::Select(args) if args.is_empty() => QueryResult::Rows(table.read_with(|table| rows(table, |_| true))?),
+        Statement::SelectWhere(condition) => {
+            QueryResult::Rows(table.read_with(|table| rows(table, |row| condition.matches(row)))?)
+        }
+        Statement::Delete(condition) => QueryResult::Deleted(table.write_with(|table| table.delete_where(|row| condition.matches(row)))?),
+        statement => return Err(format!("Unsupported statement {:?}", statement)),
+    };
+    Ok(result)
+}
+
+/// The rows matching `predicate` ordered by id
+fn rows(table: &Table, predicate: impl Fn(&Row) -> bool) -> Result<Vec<Row>, String> {
+    let mut rows = Vec::new();
+    for position in table.positions_by_id() {
+        match table.select_row(position)? {
+            Some(row) if predicate(&row) => rows.push(row),
+            _ => {}
+        }
+    }
+    Ok(rows)
+}
+
+/// The result of a function queued on a worker thread, ready once the function returns or panics
+pub struct Pending<T> {
+    state: Arc<Mutex<State<T>>>,
+}
+
+struct State<T> {
+    result: Option<T>,
+    waker: Option<Waker>,
+}
+
+impl<T: Send + 'static> Pending<Result<T, String>> {
+    /// Queues `f` on the worker behind `jobs`. A panic in `f` completes the future with an error instead
+    /// of leaving it pending forever, and the worker goes on with the next job.
+    fn spawn(jobs: &Sender<Job>, f: impl FnOnce() -> Result<T, String> + Send + 'static) -> Self {
+        let pending = Pending { state: Arc::new(Mutex::new(State { result: None, waker: None })) };
+        let state = Arc::clone(&pending.state);
+        let job: Job = Box::new(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(f))
+                .unwrap_or_else(|payload| Err(format!("Statement panicked: {}", panic_message(&*payload))));
+            complete(&state, result);
+        });
+        if jobs.send(job).is_err() {
+            complete(&pending.state, Err(String::from("The worker thread has stopped")));
+        }
+        pending
+    }
+}
+
+fn complete<T>(state: &Mutex<State<T>>, result: T) {
+    let mut state = state.lock().unwrap_or_else(PoisonError::into_inner);
+    state.result = Some(result);
+    if let Some(waker) = state.waker.take() {
+        waker.wake();
+    }
+}
+
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    match payload.downcast_ref::<&str>() {
+        Some(message) => message,
+        None => payload.downcast_ref::<String>().map_or("unknown cause", String::as_str),
+    }
+}
+
+impl<T> Future for Pending<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let mut state = self.state.lock().unwrap_or_else(PoisonError::into_inner);
+        match state.result.take() {
+            Some(result) => Poll::Ready(result),
+            None => {
+                state.waker = Some(cx.waker().clone());
+                Poll::Pending
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::future::Future;
+    use std::pin::Pin;
+    use std::task::{Context, Poll, Waker};
+    use std::thread;
+
+    use crate::nonblocking::{AsyncDatabase, Pending, QueryResult};
+    use crate::table::Table;
+
+    fn wait<T>(mut pending: Pending<T>) -> T {
+        let mut cx = Context::from_waker(Waker::noop());
+        loop {
+            match Pin::new(&mut pending).poll(&mut cx) {
+                Poll::Ready(result) => return result,
+                Poll::Pending => thread::yield_now(),
+            }
+        }
+    }
+
+    #[test]
+    fn panic_completes_with_error() {
+        let db = AsyncDatabase::new(Table::new());
+        let pending = Pending::spawn(&db.jobs, || -> Result<(), String> { panic!("boom") });
+        assert_eq!(wait(pending), Err(String::from("Statement panicked: boom")));
+
+        // the worker goes on with the next statement
+        assert_eq!(wait(db.execute("insert 1 al al@x.io")), Ok(QueryResult::Inserted(0)));
+    }
+}
@@ -0,0 +1,242 @@
+use crate::row::{ColumnName, Row, RowRef, StringEncoding, COLUMNS, EMAIL_OFFSET, EMAIL_SIZE, ID_SIZE, USERNAME_OFFSET, USERNAME_SIZE};
+use crate::schema::Schema;
+
+pub const DEFAULT_MAX_WIDTH: usize = 40;
+
+const ELLIPSIS: char = '…';
+const HEX_BYTES_PER_LINE: usize = 16;
+
+/// How the REPL prints the rows returned by a select
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputMode {
+    /// One `(id, username, email)` tuple per line
+    List,
+    /// An aligned table with a header, values longer than `max_width` are cut off with an ellipsis
+    Table { max_width: usize },
+    /// One line of tab-separated values per row, without a header. Tabs, line breaks and backslashes in
+    /// values are escaped as `\t`, `\n`, `\r` and `\\`, so every line splits into one field per column.
+    Tsv,
+}
+
+pub fn format_rows(rows: &[Row], mode: &OutputMode) -> String {
+    format_columns(rows, &COLUMNS, mode, &Schema::default())
+}
+
+/// Formats only the given columns of the rows, in the given order. Table headers name the columns as in
+/// `schema`.
+pub fn format_columns(rows: &[Row], columns: &[ColumnName], mode: &OutputMode, schema: &Schema) -> String {
+    let cells = rows.iter().map(|row| columns.iter().map(|column| column.value(row)).collect::<Vec<String>>());
+    match mode {
+        OutputMode::List => cells.map(|values| format!("({})\n", values.join(", "))).collect(),
+        OutputMode::Tsv => cells
+            .map(|values| format!("{}\n", values.iter().map(|value| escape_tsv(value)).collect::<Vec<_>>().join("\t")))
+            .collect(),
+        OutputMode::Table { max_width } => {
+            let cells: Vec<Vec<String>> = cells
+                .map(|values| values.iter().map(|value| truncate(value, *max_width)).collect())
+                .collect();
+            format_table(&cells, columns, schema)
+        }
+    }
+}
+
+/// Formats the rows in pages of at most `page_size` rows, 0 puts all rows on a single page. In table mode
+/// every page starts with the header.
+pub fn format_pages(rows: &[Row], columns: &[ColumnName], mode: &OutputMode, schema: &Schema, page_size: usize) -> Vec<String> {
+    if page_size == 0 {
+        return vec![format_columns(rows, columns, mode, schema)];
+    }
+    rows.chunks(page_size).map(|page| format_columns(page, columns, mode, schema)).collect()
+}
+
+/// Lists the fields of a serialized row with their offsets and decoded values, each followed by its raw
+/// bytes in hex. Fields that cannot be decoded show the error instead of the value.
+pub fn format_row_bytes(bytes: &[u8], encoding: StringEncoding) -> Result<String, String> {
+    let row_ref = RowRef::with_encoding(bytes, encoding)?;
+    let fields = [
+        ("id", 0, ID_SIZE, Ok(row_ref.id().to_string())),
+        ("username", USERNAME_OFFSET, USERNAME_SIZE, row_ref.username().map(String::from)),
+        ("email", EMAIL_OFFSET, EMAIL_SIZE, row_ref.email().map(String::from)),
+    ];
+
+    let mut out = String::new();
+    for (name, offset, size, value) in fields {
+        let value = value.unwrap_or_else(|e| format!("<{}>", e));
+        out.push_str(&format!("{} at byte {}, {} bytes: {}\n", name, offset, size, value));
+        out.push_str(&hex_dump(&bytes[offset..offset + size], offset));
+    }
+    Ok(out)
+}
+
+/// Formats the bytes in lines of `HEX_BYTES_PER_LINE`, each starting with the offset of its first byte
+fn hex_dump(bytes: &[u8], start_offset: usize) -> String {
+    bytes.chunks(HEX_BYTES_PER_LINE)
+        .enumerate()
+        .map(|(i, chunk)| {
+            let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+            format!("  {:04x}  {}\n", start_offset + i * HEX_BYTES_PER_LINE, hex.join(" "))
+        })
+        .collect()
+}
+
+fn format_table(cells: &[Vec<String>], columns: &[ColumnName], schema: &Schema) -> String {
+    if cells.is_empty() {
+        return String::new();
+    }
+
+    // every column is as wide as its widest value, including the header
+    let header: Vec<&str> = columns.iter().map(|&column| schema.name(column)).collect();
+    let mut widths: Vec<usize> = header.iter().map(|name| name.chars().count()).collect();
+    for row in cells.iter() {
+        for (width, cell) in widths.iter_mut().zip(row.iter()) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut out = format_line(&header, &widths);
+    let separator: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    out.push_str(&separator.join("-+-"));
+    out.push('\n');
+    for row in cells.iter() {
+        out.push_str(&format_line(row, &widths));
+    }
+    out
+}
+
+fn format_line<S: AsRef<str>>(cells: &[S], widths: &[usize]) -> String {
+    let padded: Vec<String> = cells.iter().zip(widths.iter())
+        .map(|(cell, width)| format!("{:width$}", cell.as_ref(), width = width))
+        .collect();
+    format!("{}\n", padded.join(" | ").trim_end())
+}
+
+fn escape_tsv(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+fn truncate(value: &str, max_width: usize) -> String {
+    if value.chars().count() <= max_width {
+        return value.to_string();
+    }
+    let mut truncated: String = value.chars().take(max_width.saturating_sub(1)).collect();
+    truncated.push(ELLIPSIS);
+    truncated
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{format_columns, format_pages, format_row_bytes, format_rows, OutputMode};
+    use crate::row::{ColumnName, Row, StringEncoding, COLUMNS, ROW_SIZE};
+    use crate::schema::Schema;
+
+    #[test]
+    fn table_is_aligned() -> Result<(), String> {
+        let rows = [
+            Row::from_string("1 al al@example.com")?,
+            Row::from_string("1000 bartholomew b@x.io")?,
+        ];
+
+        let expected = "\
+id   | username    | email
+-----+-------------+---------------
+1    | al          | al@example.com
+1000 | bartholomew | b@x.io
+";
+        assert_eq!(format_rows(&rows, &OutputMode::Table { max_width: 40 }), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn table_truncates_long_values() -> Result<(), String> {
+        let rows = [Row::from_string("7 someone someone@averyveryverylongdomain.com")?];
+
+        let expected = "\
+id | username | email
+---+----------+-----------
+7  | someone  | someone@a…
+";
+        assert_eq!(format_rows(&rows, &OutputMode::Table { max_width: 10 }), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn list() -> Result<(), String> {
+        let rows = [Row::from_string("1 al al@example.com")?, Row::from_string("2 bo bo@example.com")?];
+        assert_eq!(format_rows(&rows, &OutputMode::List), "(1, al, al@example.com)\n(2, bo, bo@example.com)\n");
+        assert_eq!(format_rows(&[], &OutputMode::Table { max_width: 10 }), "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn tsv() -> Result<(), String> {
+        let rows = [Row::from_string("1 al al@example.com")?, Row::from_string("2 bo bo@example.com")?];
+        assert_eq!(format_rows(&rows, &OutputMode::Tsv).as_bytes(), b"1\tal\tal@example.com\n2\tbo\tbo@example.com\n");
+
+        let row = Row::new(3, "a\tb\\c", "x\ny@z")?;
+        assert_eq!(format_rows(&[row], &OutputMode::Tsv), "3\ta\\tb\\\\c\tx\\ny@z\n");
+
+        Ok(())
+    }
+
+    #[test]
+    fn pages() -> Result<(), String> {
+        let rows: Vec<Row> = (1..=5).map(|id| Row::from_string(&format!("{} u{} u{}@x.io", id, id, id))).collect::<Result<_, _>>()?;
+
+        let pages = format_pages(&rows, &COLUMNS, &OutputMode::List, &Schema::default(), 2);
+        assert_eq!(pages, ["(1, u1, u1@x.io)\n(2, u2, u2@x.io)\n", "(3, u3, u3@x.io)\n(4, u4, u4@x.io)\n", "(5, u5, u5@x.io)\n"]);
+
+        assert_eq!(format_pages(&rows, &COLUMNS, &OutputMode::List, &Schema::default(), 0), [format_rows(&rows, &OutputMode::List)]);
+        for page in format_pages(&rows, &COLUMNS, &OutputMode::Table { max_width: 10 }, &Schema::default(), 2) {
+            assert!(page.starts_with("id | username | email\n"));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn columns() -> Result<(), String> {
+        let rows = [Row::from_string("1 al al@example.com")?, Row::from_string("20 bo bo@example.com")?];
+        let columns = [ColumnName::Email, ColumnName::Id];
+
+        assert_eq!(format_columns(&rows, &columns, &OutputMode::List, &Schema::default()), "(al@example.com, 1)\n(bo@example.com, 20)\n");
+        let expected = "\
+email          | id
+---------------+---
+al@example.com | 1
+bo@example.com | 20
+";
+        assert_eq!(format_columns(&rows, &columns, &OutputMode::Table { max_width: 40 }, &Schema::default()), expected);
+
+        Ok(())
+    }
+
+    #[test]
+    fn row_bytes() -> Result<(), String> {
+        let bytes = Row::from_string("258 al al@example.com")?.serialize();
+        let dump = format_row_bytes(&bytes, StringEncoding::NulTerminated)?;
+
+        assert!(dump.starts_with("id at byte 0, 4 bytes: 258\n  0000  02 01 00 00\nusername at byte 4, 32 bytes: al\n  0004  61 6c 00"));
+        assert!(dump.contains("email at byte 36, 255 bytes: al@example.com\n  0024  61 6c 40"));
+        let hex_bytes = dump.lines()
+            .filter(|line| line.starts_with("  "))
+            .map(|line| line.split_whitespace().skip(1).count())
+            .sum::<usize>();
+        assert_eq!(hex_bytes, ROW_SIZE);
+        assert!(format_row_bytes(&bytes[1..], StringEncoding::NulTerminated).is_err());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,581 @@
+use std::fmt;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::{self, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::DbError;
+use crate::header::{Header, FORMAT_VERSION, HEADER_SIZE};
+use crate::migrate::migrate;
+use crate::row::ROW_SIZE;
+
+/// The default page size
+pub const PAGE_SIZE: usize = 4096;
+pub const PAGE_CHECKSUM_SIZE: usize = 4;
+/// Bytes of a default-sized page available for rows, the CRC32 of these bytes is stored in the last
+/// `PAGE_CHECKSUM_SIZE` bytes
+pub const PAGE_DATA_SIZE: usize = PAGE_SIZE - PAGE_CHECKSUM_SIZE;
+/// The smallest page size that fits a row and the checksum
+pub const MIN_PAGE_SIZE: usize = ROW_SIZE + PAGE_CHECKSUM_SIZE;
+pub const MAX_PAGE_SIZE: usize = 65536;
+
+pub type Page = Vec<u8>;
+
+/// What opening a file does when its data ends within a page, e.g. after a crash during a write
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OnTruncated {
+    /// Fail with `DbError::TruncatedFile`
+    #[default]
+    Error,
+    /// Drop the incomplete last page along with the rows recorded in it
+    Drop,
+}
+
+/// Where a table keeps its pages. `MemoryStorage` holds them in memory only, `FileStorage` also writes
+/// them to a file on flush.
+pub trait Storage: fmt::Debug + Send + Sync {
+    /// Path of the backing file, `None` if there is none
+    fn path(&self) -> Option<&Path>;
+
+    /// The header as of the last open or flush
+    fn header(&self) -> &Header;
+
+    fn page_size(&self) -> usize;
+
+    /// Bytes of a page available for rows
+    fn page_data_size(&self) -> usize {
+        self.page_size() - PAGE_CHECKSUM_SIZE
+    }
+
+    fn num_pages(&self) -> usize;
+
+    fn page(&self, page_num: usize) -> Option<&[u8]>;
+
+    /// Returns the page with the given number, allocating a new page if it is the next one
+    fn page_mut(&mut self, page_num: usize) -> Option<&mut [u8]>;
+
+    /// Drops the pages from `num_pages` on, a backing file shrinks on the next flush
+    fn truncate_pages(&mut self, num_pages: usize);
+
+    /// Drops all pages and empties the backing file, if any
+    fn truncate(&mut self) -> io::Result<()>;
+
+    /// The contents a file would have after a flush with `header`: the header followed by all pages with
+    /// up to date checksums
+    fn image(&self, header: Header) -> Vec<u8>;
+
+    /// Records `header` and writes the header followed by all pages to the backing file, if any
+    fn flush(&mut self, header: Header) -> io::Result<()>;
+
+    /// Bytes of an incomplete last page that were dropped when the storage was opened
+    fn dropped_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// Pages held in memory only
+#[derive(Debug)]
+pub struct MemoryStorage {
+    header: Header,
+    page_size: usize,
+    pages: Vec<Page>,
+    // pages modified since they were last read or written, their checksums are outdated
+    dirty: Vec<bool>,
+}
+
+impl MemoryStorage {
+    /// Creates an empty storage with pages of `page_size` bytes, see `check_page_size`
+    pub fn new(page_size: usize) -> Self {
+        MemoryStorage {
+            header: Header { page_size: page_size as u32, ..Header::new(0) },
+            page_size,
+            pages: Vec::new(),
+            dirty: Vec::new(),
+        }
+    }
+
+    /// Reads an image in the file format, see `Storage::image`
+    pub fn from_image(bytes: &[u8]) -> Result<Self, DbError> {
+        let (storage, _) = MemoryStorage::load(bytes.to_vec(), PAGE_SIZE, OnTruncated::Error)?;
+        storage.verify()?;
+        Ok(storage)
+    }
+
+    /// Decodes the contents of a file, see `load`. Migrated and zero-padded pages are marked as modified.
+    fn load(bytes: Vec<u8>, page_size: usize, on_truncated: OnTruncated) -> Result<(Self, usize), DbError> {
+        let (header, pages, dirty, dropped) = load(bytes, page_size, on_truncated)?;
+        let storage = MemoryStorage {
+            header,
+            page_size: header.page_size as usize,
+            dirty,
+            pages,
+        };
+        Ok((storage, dropped))
+    }
+
+    /// Checks the checksum of every page that was not modified since it was last read or written
+    pub fn verify(&self) -> Result<(), DbError> {
+        let data_size = self.page_data_size();
+        for (page_num, page) in self.pages.iter().enumerate() {
+            if !self.dirty[page_num] && stored_checksum(page) != crc32(&page[..data_size]) {
+                return Err(DbError::Corruption { page: page_num });
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores the checksums of the modified pages in their last bytes
+    fn update_checksums(&mut self) {
+        let data_size = self.page_data_size();
+        for (page, dirty) in self.pages.iter_mut().zip(self.dirty.iter_mut()) {
+            if *dirty {
+                let checksum = crc32(&page[..data_size]);
+                page[data_size..].copy_from_slice(&checksum.to_le_bytes());
+                *dirty = false;
+            }
+        }
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn path(&self) -> Option<&Path> {
+        None
+    }
+
+    fn header(&self) -> &Header {
+        &self.header
+    }
+
+    fn page_size(&self) -> usize {
+        self.page_size
+    }
+
+    fn num_pages(&self) -> usize {
+        self.pages.len()
+    }
+
+    fn page(&self, page_num: usize) -> Option<&[u8]> {
+        self.pages.get(page_num).map(Vec::as_slice)
+    }
+
+    fn page_mut(&mut self, page_num: usize) -> Option<&mut [u8]> {
+        if page_num == self.pages.len() {
+            self.pages.push(vec![0; self.page_size]);
+            self.dirty.push(true);
+        }
+        let page = self.pages.get_mut(page_num)?;
+        self.dirty[page_num] = true;
+        Some(page.as_mut_slice())
+    }
+
+    fn truncate_pages(&mut self, num_pages: usize) {
+        if num_pages < self.pages.len() {
+            trace_event!("drop_pages", num_pages = self.pages.len() - num_pages);
+        }
+        self.pages.truncate(num_pages);
+        self.dirty.truncate(num_pages);
+    }
+
+    fn truncate(&mut self) -> io::Result<()> {
+        self.pages.clear();
+        self.dirty.clear();
+        self.header = Header { page_size: self.page_size as u32, ..Header::new(0) };
+        Ok(())
+    }
+
+    fn image(&self, header: Header) -> Vec<u8> {
+        let data_size = self.page_data_size();
+        let mut bytes = header.encode().to_vec();
+        for (page, dirty) in self.pages.iter().zip(self.dirty.iter()) {
+            bytes.extend_from_slice(&page[..data_size]);
+            let checksum = if *dirty { crc32(&page[..data_size]) } else { stored_checksum(page) };
+            bytes.extend_from_slice(&checksum.to_le_bytes());
+        }
+        bytes
+    }
+
+    fn flush(&mut self, header: Header) -> io::Result<()> {
+        self.header = header;
+        Ok(())
+    }
+}
+
+/// Pages of a file, which are held in memory and written back on flush.
+///
+/// A file starts with a `Header` followed by the pages. All pages are read and their checksums verified
+/// when the file is opened. Checksums of modified pages are updated on flush.
+#[derive(Debug)]
+pub struct FileStorage {
+    file: File,
+    path: PathBuf,
+    memory: MemoryStorage,
+    dropped_bytes: usize,
+}
+
+impl FileStorage {
+    /// Opens the file at `path`, creating it if it does not exist. An existing file must start with a valid
+    /// header, files of older format versions are migrated and written back.
+    ///
+    /// New files use pages of `page_size` bytes, `PAGE_SIZE` if it is `None`. An existing file keeps its page
+    /// size, which must match `page_size` if one is given.
+    ///
+    /// A last page cut short after its rows, e.g. by a tool that did not pad it, is filled up with zeros and
+    /// written back with its checksum. A file whose data ends within the rows its header counts fails with
+    /// `DbError::TruncatedFile`, see `open_with` to recover the complete pages instead.
+    pub fn open(path: &Path, page_size: Option<usize>) -> Result<Self, DbError> {
+        FileStorage::open_with(path, page_size, OnTruncated::Error)
+    }
+
+    /// Opens the file at `path` like `open`, handling a truncated file as `on_truncated` says. Dropped
+    /// bytes are left in the file until the next flush.
+    ///
+    /// The file is locked exclusively until the storage is dropped, a file another storage holds fails
+    /// with `DbError::Locked`.
+    pub fn open_with(path: &Path, page_size: Option<usize>, on_truncated: OnTruncated) -> Result<Self, DbError> {
+        trace_span!("open", path = path.display());
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        lock(&file, false)?;
+        FileStorage::read(file, path, page_size, on_truncated, true)
+    }
+
+    /// Opens the existing file at `path` like `open_with` without ever writing to it. A file of an older
+    /// format version is migrated in memory only. Flushing the storage fails. The file is locked shared,
+    /// so other read-only storages may open it while writers fail with `DbError::Locked`.
+    pub fn open_read_only(path: &Path, page_size: Option<usize>, on_truncated: OnTruncated) -> Result<Self, DbError> {
+        trace_span!("open", path = path.display());
+        let file = OpenOptions::new().read(true).open(path)?;
+        lock(&file, true)?;
+        FileStorage::read(file, path, page_size, on_truncated, false)
+    }
+
+    /// Reads the pages of `file`, writing migrated pages back to it if `writable`
+    fn read(mut file: File, path: &Path, page_size: Option<usize>, on_truncated: OnTruncated, writable: bool) -> Result<Self, DbError> {
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes)?;
+
+        let (memory, dropped_bytes) = MemoryStorage::load(bytes, page_size.unwrap_or(PAGE_SIZE), on_truncated)?;
+        trace_event!("load_pages", num_pages = memory.pages.len());
+        if let Some(expected) = page_size.filter(|&expected| expected != memory.page_size) {
+            return Err(DbError::PageSizeMismatch { expected, found: memory.page_size });
+        }
+        let migrated = memory.dirty.iter().any(|&dirty| dirty);
+        let header = memory.header;
+        let mut storage = FileStorage { file, path: path.to_path_buf(), memory, dropped_bytes };
+        if migrated && writable {
+            storage.flush(header)?;
+        } else {
+            storage.verify()?;
+        }
+        Ok(storage)
+    }
+
+    /// Checks the checksum of every page that was not modified since it was last read or written
+    pub fn verify(&self) -> Result<(), DbError> {
+        self.memory.verify()
+    }
+}
+
+impl Storage for FileStorage {
+    fn path(&self) -> Option<&Path> {
+        Some(&self.path)
+    }
+
+    fn header(&self) -> &Header {
+        self.memory.header()
+    }
+
+    fn page_size(&self) -> usize {
+        self.memory.page_size()
+    }
+
+    fn num_pages(&self) -> usize {
+        self.memory.num_pages()
+    }
+
+    fn page(&self, page_num: usize) -> Option<&[u8]> {
+        self.memory.page(page_num)
+    }
+
+    fn page_mut(&mut self, page_num: usize) -> Option<&mut [u8]> {
+        self.memory.page_mut(page_num)
+    }
+
+    fn truncate_pages(&mut self, num_pages: usize) {
+        self.memory.truncate_pages(num_pages)
+    }
+
+    fn truncate(&mut self) -> io::Result<()> {
+        self.memory.truncate()?;
+        self.file.set_len(0)?;
+        self.file.sync_data()
+    }
+
+    fn image(&self, header: Header) -> Vec<u8> {
+        self.memory.image(header)
+    }
+
+    /// Updates the checksums of modified pages and writes the header followed by all pages to the file
+    fn flush(&mut self, header: Header) -> io::Result<()> {
+        self.memory.flush(header)?;
+        self.memory.update_checksums();
+
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(&header.encode())?;
+        for page in &self.memory.pages {
+            self.file.write_all(page)?;
+        }
+        self.file.set_len((HEADER_SIZE + self.memory.pages.len() * self.memory.page_size) as u64)?;
+        self.file.sync_data()?;
+
+        Ok(())
+    }
+
+    fn dropped_bytes(&self) -> usize {
+        self.dropped_bytes
+    }
+}
+
+/// Decodes the header and pages of a file's contents, migrating older format versions. Empty contents
+/// get a header for pages of `page_size` bytes. Pages past the ones holding the rows the header counts
+/// are dropped. The returned flags mark the pages whose checksums are not computed yet: all pages if they
+/// were migrated, or a last page that ended after its rows and was padded with zeros. The last value is
+/// the number of bytes of an incomplete last page dropped as `on_truncated` says.
+fn load(mut bytes: Vec<u8>, page_size: usize, on_truncated: OnTruncated) -> Result<(Header, Vec<Page>, Vec<bool>, usize), DbError> {
+    let (mut header, mut data, migrated) = if bytes.is_empty() {
+        (Header { page_size: page_size as u32, ..Header::new(0) }, bytes, false)
+    } else {
+        let header = Header::decode(&bytes)?;
+        let data = bytes.split_off(HEADER_SIZE);
+        if header.version < FORMAT_VERSION {
+            let (header, data) = migrate(header, data)?;
+            (header, data, true)
+        } else {
+            (header, data, false)
+        }
+    };
+    header.validate()?;
+
+    let page_size = header.page_size as usize;
+    let rows_per_page = (page_size - PAGE_CHECKSUM_SIZE) / ROW_SIZE;
+    let num_rows = header.num_rows as usize;
+    let needed_pages = num_rows.div_ceil(rows_per_page);
+    let complete_pages = data.len() / page_size;
+    let partial_len = data.len() % page_size;
+    // pages past the rows hold nothing, e.g. when a crash came after the header of a shrinking table was
+    // written but before the file was cut to its new length
+    if complete_pages >= needed_pages {
+        data.truncate(needed_pages * page_size);
+    }
+    // the last page holding rows may end right after them
+    let padded = complete_pages + 1 == needed_pages && (num_rows - complete_pages * rows_per_page) * ROW_SIZE <= partial_len;
+    if padded {
+        data.resize(needed_pages * page_size, 0);
+    }
+
+    // a corrupt header may count more rows than any file can hold, which is a very truncated file
+    let expected_len = needed_pages.saturating_mul(page_size);
+    let missing = if data.len() < expected_len {
+        expected_len - data.len()
+    } else {
+        (page_size - data.len() % page_size) % page_size
+    };
+    let mut dropped = 0;
+    if missing > 0 {
+        match on_truncated {
+            OnTruncated::Error => return Err(DbError::TruncatedFile { bytes: missing }),
+            OnTruncated::Drop => {
+                dropped = partial_len;
+                header.num_rows = header.num_rows.min((complete_pages * rows_per_page) as u64);
+            }
+        }
+    }
+
+    let pages: Vec<Page> = data.chunks_exact(page_size).map(<[u8]>::to_vec).collect();
+    let mut dirty = vec![migrated; pages.len()];
+    if let (true, Some(last)) = (padded, dirty.last_mut()) {
+        *last = true;
+    }
+    Ok((header, pages, dirty, dropped))
+}
+
+/// Takes an advisory lock on the file that lasts until it is closed, a shared one if `shared`. Fails with
+/// `DbError::Locked` if another open file holds a conflicting lock, even within the same process.
+fn lock(file: &File, shared: bool) -> Result<(), DbError> {
+    let locked = if shared { file.try_lock_shared() } else { file.try_lock() };
+    match locked {
+        Ok(()) => Ok(()),
+        Err(TryLockError::WouldBlock) => Err(DbError::Locked),
+        Err(TryLockError::Error(e)) => Err(DbError::Io(e)),
+    }
+}
+
+/// Checks that pages of `page_size` bytes can hold at least one row and are not larger than `MAX_PAGE_SIZE`
+pub fn check_page_size(page_size: usize) -> Result<(), DbError> {
+    if !(MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size) {
+        return Err(DbError::InvalidPageSize(page_size));
+    }
+    Ok(())
+}
+
+fn stored_checksum(page: &[u8]) -> u32 {
+    let mut checksum_bytes = [0; PAGE_CHECKSUM_SIZE];
+    checksum_bytes.copy_from_slice(&page[page.len() - PAGE_CHECKSUM_SIZE..]);
+    u32::from_le_bytes(checksum_bytes)
+}
+
+/// CRC-32 (IEEE 802.3) as used by zlib and PNG
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &b in bytes {
+        crc ^= b as u32;
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xEDB8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use crate::error::DbError;
+    use crate::header::{Header, HEADER_SIZE};
+    use crate::pager::{crc32, load, FileStorage, MemoryStorage, OnTruncated, Storage, PAGE_SIZE};
+    use crate::table::ROWS_PER_PAGE;
+
+    #[test]
+    fn crc32_check_value() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(b""), 0);
+    }
+
+    #[test]
+    fn detects_corrupt_page() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-corrupt-page-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut storage = FileStorage::open(&path, None)?;
+        storage.page_mut(0).unwrap()[0] = 1;
+        storage.page_mut(1).unwrap()[0] = 2;
+        storage.flush(Header::new(15)).map_err(|e| e.to_string())?;
+
+        drop(storage);
+        let mut storage = FileStorage::open(&path, None)?;
+        storage.verify()?;
+        storage.memory.pages[1][100] ^= 0x01;
+        assert!(matches!(storage.verify(), Err(DbError::Corruption { page: 1 })));
+
+        // the corruption is also detected when the file is read again
+        storage.memory.dirty[1] = true;
+        storage.flush(Header::new(15)).map_err(|e| e.to_string())?;
+        drop(storage);
+        let mut bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+        let len = bytes.len();
+        bytes[len - 10] ^= 0x01;
+        std::fs::write(&path, bytes).map_err(|e| e.to_string())?;
+        assert!(matches!(FileStorage::open(&path, None), Err(DbError::Corruption { page: 1 })));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn huge_row_count() -> Result<(), String> {
+        let header = Header { num_rows: u64::MAX, ..Header::new(0) };
+        let mut bytes = header.encode().to_vec();
+        bytes.resize(HEADER_SIZE + PAGE_SIZE, 0);
+        assert!(matches!(load(bytes.clone(), PAGE_SIZE, OnTruncated::Error), Err(DbError::TruncatedFile { .. })));
+
+        let (header, pages, _, _) = load(bytes, PAGE_SIZE, OnTruncated::Drop)?;
+        assert_eq!(pages.len(), 1);
+        assert_eq!(header.num_rows, ROWS_PER_PAGE as u64);
+
+        Ok(())
+    }
+
+    #[test]
+    fn detects_truncated_file() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-truncated-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // 20 rows take up two pages
+        let mut storage = FileStorage::open(&path, None)?;
+        storage.page_mut(0).unwrap()[0] = 1;
+        storage.page_mut(1).unwrap()[0] = 2;
+        storage.flush(Header::new(20)).map_err(|e| e.to_string())?;
+        drop(storage);
+        let len = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        let file = std::fs::OpenOptions::new().write(true).open(&path).map_err(|e| e.to_string())?;
+        // the last page ends within its 6 rows
+        file.set_len(len - PAGE_SIZE as u64 + 1000).map_err(|e| e.to_string())?;
+
+        assert!(matches!(FileStorage::open(&path, None), Err(DbError::TruncatedFile { bytes }) if bytes == PAGE_SIZE - 1000));
+
+        let storage = FileStorage::open_with(&path, None, OnTruncated::Drop)?;
+        assert_eq!(storage.num_pages(), 1);
+        assert_eq!(storage.header().num_rows, 14);
+        assert_eq!(storage.dropped_bytes(), 1000);
+        storage.verify()?;
+        drop(storage);
+
+        // a header counting rows for pages the file does not have is caught as well
+        file.set_len(len - PAGE_SIZE as u64).map_err(|e| e.to_string())?;
+        assert!(matches!(FileStorage::open(&path, None), Err(DbError::TruncatedFile { bytes }) if bytes == PAGE_SIZE));
+
+        // pages and bytes past the rows the header counts are dropped, not padded into another page
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        let mut storage = FileStorage::open(&path, None)?;
+        storage.page_mut(0).unwrap()[0] = 1;
+        storage.page_mut(1).unwrap()[0] = 2;
+        storage.flush(Header::new(1)).map_err(|e| e.to_string())?;
+        drop(storage);
+        let mut file = std::fs::OpenOptions::new().append(true).open(&path).map_err(|e| e.to_string())?;
+        file.write_all(&[7; 100]).map_err(|e| e.to_string())?;
+        drop(file);
+
+        let storage = FileStorage::open(&path, None)?;
+        assert_eq!(storage.num_pages(), 1);
+        assert_eq!(storage.header().num_rows, 1);
+        assert_eq!(storage.dropped_bytes(), 0);
+        storage.verify()?;
+        drop(storage);
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn memory_and_file_storage_agree() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-storage-backends-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut storages: [Box<dyn Storage>; 2] = [Box::new(MemoryStorage::new(512)), Box::new(FileStorage::open(&path, Some(512))?)];
+        for storage in storages.iter_mut() {
+            storage.page_mut(0).unwrap()[..3].copy_from_slice(b"abc");
+            storage.page_mut(1).unwrap()[7] = 7;
+            storage.page_mut(2).unwrap()[0] = 2;
+            storage.truncate_pages(2);
+            storage.flush(Header { page_size: 512, ..Header::new(2) }).map_err(|e| e.to_string())?;
+        }
+
+        let [memory, file] = &storages;
+        assert_eq!(memory.num_pages(), 2);
+        assert_eq!(memory.image(*memory.header()), file.image(*file.header()));
+        assert_eq!(std::fs::read(&path).map_err(|e| e.to_string())?, memory.image(*memory.header()));
+        assert_eq!((memory.path(), file.path()), (None, Some(path.as_path())));
+
+        // only the restored pages carry their checksums
+        let restored = MemoryStorage::from_image(&memory.image(*memory.header()))?;
+        assert_eq!(restored.page(1).unwrap()[..508], memory.page(1).unwrap()[..508]);
+        assert_eq!(restored.page(1), file.page(1));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+}
@@ -0,0 +1,673 @@
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::path::Path;
+use std::time::Instant;
+
+use crate::error::DbError;
+use crate::explain::explain;
+use crate::output::{format_pages, format_row_bytes, format_rows, OutputMode, DEFAULT_MAX_WIDTH};
+use crate::row::{ColumnName, Row, COLUMNS};
+use crate::statement::{parse_statement_with, strip_comment, Statement};
+use crate::table::{Table, Upsert};
+use crate::util::gen_random_row;
+
+const PHYSICAL_KW: &str = "physical";
+const FIRST_KW: &str = "first";
+const LAST_KW: &str = "last";
+pub const DEFAULT_PROMPT: &str = "db> ";
+pub const HELP: &str = "\
+.backup <path>              Write an image of the table to a file
+.changes                    Show the number of rows the last insert, replace or delete changed
+.close                      Close the database file and use an in-memory table
+.compact <path>             Write the rows ordered by id to a new file without unused space
+.echo on|off                Print each input line prefixed with -- before running it
+.exit                       Write the table to its file and exit
+.fill <n>                   Insert n rows with sequential ids and random text
+.help                       Show this message
+.import <path>              Insert the rows listed in a file
+.indexes                    List the indexed columns, create index on username adds one
+.mode <mode> [width]        Set the output mode of select, list, tsv or table with a max width
+.open <path>                Write the table to its file and open another one
+.pagesize <rows>            Pause select output every <rows> rows, 0 for unlimited
+.prompt <text>              Set the prompt, put the text in double quotes to end it with a space
+.restore <path>             Replace the table with an in-memory copy of a backup
+.row <position>             Show the stored bytes of a row in hex along with its fields
+.schema                     Show the names and types of the columns
+.stats                      Show the number of rows and pages, how much of the file is unused and how
+                            many more rows fit
+.timer on|off               Show the run time of each statement
+.truncate                   Remove all rows
+.verbose on|off             Show where inserted rows are stored
+";
+
+/// Like `print!`, writing to `out` and failing the command if the write fails
+macro_rules! out {
+    ($out:expr, $($arg:tt)*) => {
+        write!($out, $($arg)*).map_err(|e| format!("Error writing output: {}", e))?
+    };
+}
+
+/// Like `println!`, writing to `out` and failing the command if the write fails
+macro_rules! outln {
+    ($out:expr, $($arg:tt)*) => {
+        writeln!($out, $($arg)*).map_err(|e| format!("Error writing output: {}", e))?
+    };
+}
+
+/// State of an interactive session
+pub struct Repl {
+    pub table: Table,
+    mode: OutputMode,
+    // print the run time of every statement
+    timer: bool,
+    // number of rows a select prints before waiting for Enter, 0 prints all rows at once
+    page_size: usize,
+    // stdin is a terminal, paging only applies to interactive sessions
+    interactive: bool,
+    // report the page and offset every inserted row is stored at
+    verbose: bool,
+    // print every input line before running it
+    echo: bool,
+    // rows changed by the last insert, replace or delete
+    changes: usize,
+    // shown before every line read from a terminal or a pipe
+    prompt: String,
+    // print only query results and errors, without prompts or confirmations of changes
+    quiet: bool,
+}
+
+impl Repl {
+    pub fn new(table: Table) -> Self {
+        Repl { table, mode: OutputMode::List, timer: false, page_size: 0, interactive: false, verbose: false, echo: false, changes: 0, prompt: DEFAULT_PROMPT.to_string(), quiet: false }
+    }
+
+    /// Replaces the default prompt `db> `
+    pub fn with_prompt(mut self, prompt: impl Into<String>) -> Self {
+        self.prompt = prompt.into();
+        self
+    }
+
+    /// Leaves out the prompt and the messages confirming inserts, deletes and `.exit`, so the output
+    /// holds nothing but query results
+    pub fn with_quiet(mut self, quiet: bool) -> Self {
+        self.quiet = quiet;
+        self
+    }
+
+    /// The prompt to show before reading a line, empty in quiet mode
+    pub fn prompt(&self) -> &str {
+        if self.quiet { "" } else { &self.prompt }
+    }
+
+    pub fn is_quiet(&self) -> bool {
+        self.quiet
+    }
+
+    /// Marks the session as reading from a terminal, which enables paging of select output
+    pub fn with_interactive(mut self, interactive: bool) -> Self {
+        self.interactive = interactive;
+        self
+    }
+}
+
+/// What the session does after a line of input was handled
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetaResult {
+    /// Read the next line
+    Continue,
+    /// End the session, the process should exit with the code
+    Exit(i32),
+}
+
+/// Runs a line of input, a meta command or a statement, writing its results to `out`
+pub fn handle_input(input: &str, repl: &mut Repl, out: &mut dyn Write) -> Result<MetaResult, String> {
+    if repl.echo && !input.trim().is_empty() {
+        outln!(out, "-- {}", input.trim());
+    }
+    if input.trim_start().starts_with('.') {
+        do_meta_command(input, repl, out)
+    } else {
+        // blank lines and lines holding nothing but a comment are skipped
+        let statement = strip_comment(input).trim();
+        if statement.is_empty() {
+            return Ok(MetaResult::Continue);
+        }
+        match parse_statement_with(statement, repl.table.schema()) {
+            Ok(statement) => {
+                let start = Instant::now();
+                let result = do_process_statement(statement, repl, out);
+                if repl.timer {
+                    outln!(out, "Run Time: {:.3} ms", start.elapsed().as_secs_f64() * 1000.0);
+                }
+                result.map(|()| MetaResult::Continue)
+            }
+            Err(error) => Err(format!("Error: {}", error)),
+        }
+    }
+}
+
+/// Runs a meta command. `.exit` writes the table to its file and returns `Exit`, leaving it to the
+/// caller to end the session.
+pub fn do_meta_command(command: &str, repl: &mut Repl, out: &mut dyn Write) -> Result<MetaResult, String> {
+    let table = &mut repl.table;
+    // the argument as given, for `.prompt` whose text may start or end with spaces
+    let raw_arg = command.trim_start().split_once(char::is_whitespace).map_or("", |(_, arg)| arg);
+    let command = command.trim();
+    // the command name is case-insensitive, the argument keeps its case since it may be a path
+    let (name, arg) = match command.split_once(char::is_whitespace) {
+        Some((name, arg)) => (name.to_lowercase(), arg.trim()),
+        None => (command.to_lowercase(), ""),
+    };
+
+    match name.as_str() {
+        ".exit" => {
+            table.flush()?;
+            if !repl.quiet {
+                outln!(out, "Exiting...");
+            }
+            Ok(MetaResult::Exit(0))
+        }
+        ".help" => {
+            out!(out, "{}", HELP);
+            Ok(MetaResult::Continue)
+        }
+        ".changes" => {
+            outln!(out, "{}", repl.changes);
+            Ok(MetaResult::Continue)
+        }
+        ".indexes" => {
+            for column in table.indexed_columns() {
+                outln!(out, "{}", table.schema().name(column));
+            }
+            Ok(MetaResult::Continue)
+        }
+        ".prompt" => {
+            if arg.is_empty() {
+                return Err(String::from("Usage: .prompt <text>"));
+            }
+            // a line editor trims the line, quotes keep the spaces at the end of a prompt
+            repl.prompt = match arg.strip_prefix('"').and_then(|arg| arg.strip_suffix('"')) {
+                Some(quoted) => quoted.to_string(),
+                None => raw_arg.to_string(),
+            };
+            Ok(MetaResult::Continue)
+        }
+        ".open" => {
+            if arg.is_empty() {
+                return Err(String::from("Usage: .open <path>"));
+            }
+            table.flush()?;
+            // a read-only session stays read-only
+            let read_only = table.is_read_only();
+            let open = |path: &Path| if read_only { Table::open_readonly(path) } else { Table::open(path) };
+            let reopened = table.path().filter(|path| is_same_file(path, Path::new(arg))).map(Path::to_path_buf);
+            if reopened.is_some() {
+                // release the lock on the file before opening it again
+                *table = Table::new();
+            }
+            match open(Path::new(arg)) {
+                Ok(opened) => *table = opened,
+                Err(e) => {
+                    let error = format!("Unable to open '{}': {}", arg, e);
+                    // the file was already closed, go back to it rather than silently continuing in memory
+                    return match reopened.map(|path| open(&path)) {
+                        Some(Ok(previous)) => {
+                            *table = previous;
+                            Err(error)
+                        }
+                        Some(Err(e)) => Err(format!("{}, and reopening it failed: {}; no file is open, the table is empty and kept in memory only", error, e)),
+                        None => Err(error),
+                    };
+                }
+            }
+            outln!(out, "Opened {} with {} rows", arg, table.num_rows());
+            Ok(MetaResult::Continue)
+        }
+        ".import" => {
+            if arg.is_empty() {
+                return Err(String::from("Usage: .import <path>"));
+            }
+            import_file(Path::new(arg), table, out)?;
+            Ok(MetaResult::Continue)
+        }
+        ".fill" => {
+            let count = arg.parse::<u32>().map_err(|_| String::from("Usage: .fill <n>"))?;
+            let start = Instant::now();
+            fill(table, count)?;
+            outln!(out, "Inserted {} rows in {:.3} ms", count, start.elapsed().as_secs_f64() * 1000.0);
+            Ok(MetaResult::Continue)
+        }
+        ".backup" => {
+            if arg.is_empty() {
+                return Err(String::from("Usage: .backup <path>"));
+            }
+            fs::write(arg, table.snapshot()).map_err(|e| format!("Unable to write '{}': {}", arg, e))?;
+            outln!(out, "Backed up {} rows to {}", table.num_rows(), arg);
+            Ok(MetaResult::Continue)
+        }
+        ".compact" => {
+            if arg.is_empty() {
+                return Err(String::from("Usage: .compact <path>"));
+            }
+            let rows = table.compact_into(Path::new(arg)).map_err(|e| format!("Unable to compact into '{}': {}", arg, e))?;
+            outln!(out, "Compacted {} rows into {}", rows, arg);
+            Ok(MetaResult::Continue)
+        }
+        ".restore" => {
+            if arg.is_empty() {
+                return Err(String::from("Usage: .restore <path>"));
+            }
+            let blob = fs::read(arg).map_err(|e| format!("Unable to read '{}': {}", arg, e))?;
+            let restored = Table::restore(&blob).map_err(|e| format!("Unable to restore '{}': {}", arg, e))?;
+            table.flush()?;
+            *table = restored;
+            outln!(out, "Restored {} rows from {} into an in-memory table", table.num_rows(), arg);
+            Ok(MetaResult::Continue)
+        }
+        ".mode" => {
+            let mut args = arg.split_whitespace();
+            repl.mode = match (args.next(), args.next()) {
+                (Some("list"), None) => OutputMode::List,
+                (Some("tsv"), None) => OutputMode::Tsv,
+                (Some("table"), None) => OutputMode::Table { max_width: DEFAULT_MAX_WIDTH },
+                (Some("table"), Some(max_width)) => match max_width.parse::<usize>() {
+                    Ok(max_width) if max_width > 0 => OutputMode::Table { max_width },
+                    _ => return Err(format!("Invalid max width: {}", max_width)),
+                },
+                _ => return Err(String::from("Usage: .mode list|tsv|table [max_width]")),
+            };
+            Ok(MetaResult::Continue)
+        }
+        ".timer" => {
+            repl.timer = match arg {
+                "on" => true,
+                "off" => false,
+                _ => return Err(String::from("Usage: .timer on|off")),
+            };
+            Ok(MetaResult::Continue)
+        }
+        ".echo" => {
+            repl.echo = match arg {
+                "on" => true,
+                "off" => false,
+                _ => return Err(String::from("Usage: .echo on|off")),
+            };
+            Ok(MetaResult::Continue)
+        }
+        ".verbose" => {
+            repl.verbose = match arg {
+                "on" => true,
+                "off" => false,
+                _ => return Err(String::from("Usage: .verbose on|off")),
+            };
+            Ok(MetaResult::Continue)
+        }
+        ".truncate" => {
+            table.truncate()?;
+            outln!(out, "Table truncated");
+            Ok(MetaResult::Continue)
+        }
+        ".pagesize" => {
+            repl.page_size = arg.parse::<usize>().map_err(|_| String::from("Usage: .pagesize <rows>, 0 for unlimited"))?;
+            Ok(MetaResult::Continue)
+        }
+        ".schema" => {
+            out!(out, "{}", table.schema().describe());
+            Ok(MetaResult::Continue)
+        }
+        ".stats" => {
+            outln!(out, "num_rows: {}", table.num_rows());
+            outln!(out, "num_pages: {}", table.num_pages());
+            outln!(out, "bytes_used: {}", table.bytes_used());
+            outln!(out, "last_page_free_bytes: {}", table.last_page_free_bytes());
+            let usage = table.disk_usage();
+            outln!(out, "file_bytes: {}", usage.total_bytes);
+            outln!(out, "dead_bytes: {}", usage.dead_bytes);
+            outln!(out, "fragmentation: {:.1}%", usage.fragmentation());
+            outln!(out, "capacity_remaining: {}", table.capacity_remaining());
+            Ok(MetaResult::Continue)
+        }
+        ".row" => {
+            let position = arg.parse::<usize>().map_err(|_| String::from("Usage: .row <position>"))?;
+            let bytes = table.row_bytes(position).ok_or_else(|| format!("No row at position {}", position))?;
+            out!(out, "{}", format_row_bytes(bytes, table.encoding())?);
+            Ok(MetaResult::Continue)
+        }
+        ".close" => {
+            table.flush()?;
+            *table = Table::new();
+            outln!(out, "Closed database, using an in-memory table");
+            Ok(MetaResult::Continue)
+        }
+        _ => {
+            Err(format!("Unknown command: {}", command))
+        }
+    }
+}
+
+/// Inserts the rows of a file holding one `insert` statement or one `<id> <username> <email>` triple
+/// per line, `--` comments are ignored. Every line is parsed before the first insert, so a malformed file inserts nothing.
+fn import_file(path: &Path, table: &mut Table, out: &mut dyn Write) -> Result<(), String> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| format!("Unable to read '{}': {}", path.display(), e))?;
+
+    let mut line_nums = Vec::new();
+    let mut rows = Vec::new();
+    for (line_idx, line) in contents.lines().enumerate() {
+        let line = strip_comment(line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        let row = if line.to_lowercase().starts_with("insert") {
+            match parse_statement_with(line, table.schema()) {
+                Ok(Statement::Insert(row)) => Ok(row),
+                Ok(_) => Err(String::from("Only insert statements can be imported")),
+                Err(e) => Err(e),
+            }
+        } else {
+            Row::from_string(line)
+        };
+        match row {
+            Ok(row) => {
+                line_nums.push(line_idx + 1);
+                rows.push(row);
+            }
+            Err(e) => return Err(format!("Import failed at line {}, no rows inserted: {}", line_idx + 1, e)),
+        }
+    }
+
+    if rows.is_empty() {
+        outln!(out, "Imported 0 rows");
+        return Ok(());
+    }
+    let num_rows_before = table.num_rows();
+    if let Err(e) = table.insert_rows(&rows) {
+        let inserted = table.num_rows() - num_rows_before;
+        return Err(match line_nums.get(inserted) {
+            Some(line_num) => format!("Import stopped at line {} after inserting {} rows: {}", line_num, inserted, e),
+            None => format!("Import failed after inserting {} rows: {}", inserted, e),
+        });
+    }
+    outln!(out, "Imported {} rows", rows.len());
+    Ok(())
+}
+
+/// Whether both paths name the same existing file, however they are spelled
+fn is_same_file(a: &Path, b: &Path) -> bool {
+    matches!((fs::canonicalize(a), fs::canonicalize(b)), (Ok(a), Ok(b)) if a == b)
+}
+
+/// Inserts `count` rows with random usernames and emails, their ids continue after the largest id in the table
+fn fill(table: &mut Table, count: u32) -> Result<(), String> {
+    let first_id = table.max_id().map_or(Some(0), |id| id.checked_add(1));
+    let ids = first_id
+        .and_then(|first_id| Some(first_id..first_id.checked_add(count)?))
+        .ok_or_else(|| format!("Unable to fill {} rows, the ids would exceed {}", count, u32::MAX))?;
+
+    let mut rng = rand::thread_rng();
+    let rows: Vec<Row> = ids.map(|id| gen_random_row(&mut rng, id)).collect();
+    table.insert_rows(&rows)?;
+    Ok(())
+}
+
+pub fn do_process_statement(statement: Statement, repl: &mut Repl, out: &mut dyn Write) -> Result<(), String> {
+    let table = &mut repl.table;
+    match statement {
+        Statement::Insert(row) => {
+            if repl.verbose {
+                let placement = table.next_placement();
+                let page = if placement.new_page { "new page" } else { "page" };
+                outln!(out, "Storing row in {} {} at byte offset {}", page, placement.page_num, placement.byte_offset);
+            }
+            match table.insert_row(&row) {
+                Ok(_) if repl.quiet => {}
+                Ok(position) => outln!(out, "Row inserted at position {}", position),
+                Err(cause) => return Err(format!("Error inserting row: {}", cause))
+            }
+            repl.changes = 1;
+        }
+        Statement::Upsert(row) => {
+            match table.upsert_row(&row) {
+                Ok(_) if repl.quiet => {}
+                Ok(Upsert::Inserted(position)) => outln!(out, "Row inserted at position {}", position),
+                Ok(Upsert::Replaced(position)) => outln!(out, "Row replaced at position {}", position),
+                Err(cause) => return Err(format!("Error inserting row: {}", cause))
+            }
+            repl.changes = 1;
+        }
+        Statement::Select(args) => {
+            if args.trim().is_empty() {
+                let rows = scan(table, table.positions_by_id())?;
+                print_rows(&rows, &COLUMNS, repl, out)?;
+            } else if args.trim().eq_ignore_ascii_case(PHYSICAL_KW) {
+                // storage order, which changes as rows are deleted
+                let rows = table.select_range(0, table.num_rows())?;
+                print_rows(&rows, &COLUMNS, repl, out)?;
+            } else if args.trim().eq_ignore_ascii_case(FIRST_KW) || args.trim().eq_ignore_ascii_case(LAST_KW) {
+                let row = if args.trim().eq_ignore_ascii_case(FIRST_KW) { table.first_by_id()? } else { table.last_by_id()? };
+                match row {
+                    Some(row) => print_rows(&[row], &COLUMNS, repl, out)?,
+                    None => outln!(out, "No rows"),
+                }
+            } else {
+                match args.trim().parse::<usize>() {
+                    Ok(row_idx) => print_table_row(table, row_idx, &repl.mode, out)?,
+                    Err(err) => return Err(format!("Error printing row for input '{}': {}", args, err))
+                }
+            }
+        }
+        Statement::Aggregate(kind) => {
+            match kind.apply(scan(table, 0..table.num_rows())?.iter().map(|row| row.id)) {
+                Some(value) => outln!(out, "{}", value),
+                None => outln!(out, "NULL"),
+            }
+        }
+        Statement::Explain(statement) => outln!(out, "{}", explain(&statement, table)?),
+        Statement::Delete(condition) => {
+            let deleted = table.delete_where(|row| condition.matches(row))?;
+            repl.changes = deleted;
+            if !repl.quiet {
+                outln!(out, "Deleted {} rows", deleted);
+            }
+        }
+        Statement::SelectColumns(columns) => {
+            let rows = scan(table, table.positions_by_id())?;
+            print_rows(&rows, &columns, repl, out)?;
+        }
+        Statement::SelectDistinct(column) => {
+            for value in table.distinct_values(column)? {
+                outln!(out, "{}", value);
+            }
+        }
+        Statement::CountGroupBy(column) => {
+            for (value, count) in table.count_by(column)? {
+                outln!(out, "({}, {})", value, count);
+            }
+        }
+        Statement::CreateIndex(column) => {
+            let name = table.schema().name(column).to_string();
+            if !table.create_username_index()? {
+                return Err(format!("Index on {} already exists", name));
+            }
+            if !repl.quiet {
+                outln!(out, "Created index on {}", name);
+            }
+        }
+        Statement::DropIndex(column) => {
+            let name = table.schema().name(column).to_string();
+            if !table.drop_username_index() {
+                return Err(format!("No index on {}", name));
+            }
+            if !repl.quiet {
+                outln!(out, "Dropped index on {}", name);
+            }
+        }
+        Statement::SelectWhere(condition) => {
+            if let Some(id) = condition.id_equals() {
+                // ids are not unique yet, every match is listed with the position it is stored at
+                for position in table.positions_of_id(id) {
+                    if let Some(row) = table.select_row(position)? {
+                        outln!(out, "{}: {}", position, row);
+                    }
+                }
+                return Ok(());
+            }
+            if let Some(positions) = condition.username_equals().and_then(|username| table.positions_of_username(username)) {
+                let mut rows = scan(table, positions.into_iter())?;
+                rows.sort_by_key(|row| row.id);
+                return print_rows(&rows, &COLUMNS, repl, out);
+            }
+            let rows = match condition.id_range() {
+                Some(range) if range.is_empty() => Vec::new(),
+                Some(range) => scan(table, table.positions_in_id_range(range))?,
+                None => scan(table, table.positions_by_id())?.into_iter().filter(|row| condition.matches(row)).collect(),
+            };
+            print_rows(&rows, &COLUMNS, repl, out)?;
+        }
+    }
+    Ok(())
+}
+
+/// Prints the rows page by page, waiting for Enter after every full page. Entering `q` skips the
+/// remaining pages. Without a terminal on stdin all rows are printed at once.
+fn print_rows(rows: &[Row], columns: &[ColumnName], repl: &Repl, out: &mut dyn Write) -> Result<(), String> {
+    let page_size = if repl.interactive { repl.page_size } else { 0 };
+    let pages = format_pages(rows, columns, &repl.mode, repl.table.schema(), page_size);
+    for (i, page) in pages.iter().enumerate() {
+        out!(out, "{}", page);
+        if i + 1 < pages.len() {
+            out!(out, "-- More (Enter to continue, q to quit) --");
+            let _ = out.flush();
+            if let Ok(Some(answer)) = read_line() {
+                if answer.eq_ignore_ascii_case("q") {
+                    break;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Reads the rows at the given positions, failing instead of panicking on corrupt data
+fn scan(table: &Table, positions: impl Iterator<Item = usize>) -> Result<Vec<Row>, DbError> {
+    positions
+        .filter_map(|position| table.select_row(position).transpose())
+        .collect()
+}
+
+fn print_table_row(table: &Table, row_idx: usize, mode: &OutputMode, out: &mut dyn Write) -> Result<(), String> {
+    let num_rows = table.num_rows();
+
+    if num_rows == 0 {
+        outln!(out, "Table is empty, nothing to print for index {}", row_idx);
+    } else if row_idx >= table.num_rows() {
+        outln!(out, "Row index out of bounds: {} is not in [0, {}]", row_idx, num_rows)
+    } else if let Some(row) = table.select_row(row_idx)? {
+        out!(out, "{}", format_rows(&[row], mode))
+    }
+    Ok(())
+}
+
+/// Reads the next line from stdin, returning `None` once stdin has reached EOF
+pub fn read_line() -> io::Result<Option<String>> {
+    let stdin = io::stdin();
+    let mut handle = stdin.lock();
+    let mut input = String::new();
+    if handle.read_line(&mut input)? == 0 {
+        return Ok(None);
+    }
+    // only the line break is removed, a `.prompt` may end in spaces
+    Ok(Some(input.trim_end_matches(['\r', '\n']).to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::repl::{do_meta_command, handle_input, MetaResult, Repl};
+    use crate::table::Table;
+
+    #[test]
+    fn writes_to_sink() -> Result<(), String> {
+        let mut repl = Repl::new(Table::new());
+        let mut out = Vec::new();
+        for line in ["insert 2 bob bob@x.io", "insert 1 al al@x.io", "select", ".stats"] {
+            handle_input(line, &mut repl, &mut out)?;
+        }
+
+        let output = String::from_utf8(out).map_err(|e| e.to_string())?;
+        assert!(output.starts_with("Row inserted at position 0\nRow inserted at position 1\n(1, al, al@x.io)\n(2, bob, bob@x.io)\n"));
+        assert!(output.contains("num_rows: 2\n"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn open_same_file() -> Result<(), String> {
+        let dir = std::env::temp_dir();
+        let name = format!("dbrs-open-same-{}.db", std::process::id());
+        let path = dir.join(&name);
+        let _ = std::fs::remove_file(&path);
+
+        let mut repl = Repl::new(Table::open(&path)?);
+        let mut out = Vec::new();
+        handle_input("insert 1 al al@x.io", &mut repl, &mut out)?;
+        // another spelling of the path is recognized as the open file instead of failing on its lock
+        let spelled = dir.join("..").join(dir.strip_prefix("/").map_err(|e| e.to_string())?).join(&name);
+        handle_input(&format!(".open {}", spelled.display()), &mut repl, &mut out)?;
+        assert_eq!(repl.table.num_rows(), 1);
+
+        // a file that can no longer be opened leaves no file open and says so
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        std::fs::create_dir(&path).map_err(|e| e.to_string())?;
+        let err = handle_input(&format!(".open {}", path.display()), &mut repl, &mut out).unwrap_err();
+        assert!(err.ends_with("no file is open, the table is empty and kept in memory only"), "{}", err);
+        assert!(repl.table.path().is_none());
+        assert_eq!(repl.table.num_rows(), 0);
+
+        std::fs::remove_dir(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn exit_is_left_to_the_caller() -> Result<(), String> {
+        let mut repl = Repl::new(Table::new());
+        let mut out = Vec::new();
+        assert_eq!(handle_input("insert 1 al al@x.io", &mut repl, &mut out)?, MetaResult::Continue);
+        assert_eq!(do_meta_command(".exit", &mut repl, &mut out)?, MetaResult::Exit(0));
+        assert!(String::from_utf8_lossy(&out).ends_with("Exiting...\n"));
+        assert_eq!(repl.table.num_rows(), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn changes() -> Result<(), String> {
+        let mut repl = Repl::new(Table::new());
+        let mut out = Vec::new();
+        for line in [".changes", "insert 1 al al@x.io", ".changes", "select", ".changes", "insert 2 bo bo@x.io", "delete where id < 5", ".changes"] {
+            handle_input(line, &mut repl, &mut out)?;
+        }
+
+        let output = String::from_utf8(out).map_err(|e| e.to_string())?;
+        let counts: Vec<&str> = output.lines().filter(|line| line.parse::<usize>().is_ok()).collect();
+        assert_eq!(counts, ["0", "1", "1", "2"]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn fill() -> Result<(), String> {
+        let mut repl = Repl::new(Table::new());
+        let mut out = Vec::new();
+        handle_input("insert 7 al al@x.io", &mut repl, &mut out)?;
+        handle_input(".fill 50", &mut repl, &mut out)?;
+
+        assert_eq!(repl.table.num_rows(), 51);
+        let ids: Vec<u32> = repl.table.iter().skip(1).map(|row| row.id).collect();
+        assert_eq!(ids, (8..58).collect::<Vec<u32>>());
+        assert!(repl.table.iter().all(|row| row.email.split_once('@').is_some_and(|(name, domain)| !name.is_empty() && !domain.is_empty())));
+        assert!(String::from_utf8_lossy(&out).contains("Inserted 50 rows in "));
+        assert!(handle_input(".fill many", &mut repl, &mut out).is_err());
+
+        Ok(())
+    }
+}
@@ -1,7 +1,17 @@
 use std::cmp;
+use std::fmt;
+use std::num::IntErrorKind;
+use std::str::{self, Utf8Error};
 
+use crate::error::DbError;
+use crate::schema::Schema;
+
+use crate::token::{tokenize, Token};
+
+/// Rows order by id, then username, then email. Build rows with `Row::new`, which checks that the fields
+/// fit their slots, since struct literals skip the checks.
 #[derive(Debug)]
-#[derive(PartialEq)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Row {
     pub id: u32,
     pub username: String,
@@ -15,62 +25,549 @@ pub const EMAIL_OFFSET: usize = USERNAME_OFFSET + USERNAME_SIZE;
 pub const EMAIL_SIZE: usize = 255;
 pub const ROW_SIZE: usize = ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
 
+/// The fields of a row in the order statements list them
+pub const INSERT_USAGE: &str = "usage: insert <id:int> <username> <email>";
+
+/// A column of the table, as named in statements
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColumnName {
+    Id,
+    Username,
+    Email,
+}
+
+/// All columns in the order they are stored
+pub const COLUMNS: [ColumnName; 3] = [ColumnName::Id, ColumnName::Username, ColumnName::Email];
+
+impl ColumnName {
+    /// Looks up a column by name, ignoring case
+    pub fn parse(name: &str) -> Option<Self> {
+        COLUMNS.into_iter().find(|column| column.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ColumnName::Id => "id",
+            ColumnName::Username => "username",
+            ColumnName::Email => "email",
+        }
+    }
+
+    pub fn value(&self, row: &Row) -> String {
+        match self {
+            ColumnName::Id => row.id.to_string(),
+            ColumnName::Username => row.username.clone(),
+            ColumnName::Email => row.email.clone(),
+        }
+    }
+}
+
+/// How the text fields are stored in their fixed-size slots.
+///
+/// `NulTerminated` strings end at the first NUL byte or fill the whole slot, so a string containing a
+/// NUL byte is cut short when read back. `LengthPrefixed` strings start with a one byte length and may
+/// contain any bytes, at the cost of one byte of the slot.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum StringEncoding {
+    #[default]
+    NulTerminated,
+    LengthPrefixed,
+}
+
+impl StringEncoding {
+    /// Number of bytes of a string that fit a slot of `slot_size` bytes
+    pub fn capacity(&self, slot_size: usize) -> usize {
+        match self {
+            StringEncoding::NulTerminated => slot_size,
+            StringEncoding::LengthPrefixed => slot_size - 1,
+        }
+    }
+}
+
 impl Row {
+    /// A row whose username and email fit their slots of `USERNAME_SIZE` and `EMAIL_SIZE` bytes and whose
+    /// email has a single `@` with text on both sides. That is the room NUL-terminated strings have,
+    /// length-prefixed strings fit one byte less, which tables check on insert with `check_fits`.
+    pub fn new(id: u32, username: impl Into<String>, email: impl Into<String>) -> Result<Self, DbError> {
+        let row = Row { id, username: username.into(), email: email.into() };
+        row.check_fits(StringEncoding::NulTerminated)?;
+        match row.email.split_once('@') {
+            Some((local, domain)) if !local.is_empty() && !domain.is_empty() && !domain.contains('@') => {}
+            _ => return Err(DbError::InvalidRow(format!("Invalid email '{}', expected <name>@<domain>", row.email))),
+        }
+        Ok(row)
+    }
+
+    /// Checks that the username and email fit their slots when stored with `encoding`
+    pub fn check_fits(&self, encoding: StringEncoding) -> Result<(), DbError> {
+        for (name, value, slot_size) in [("Username", &self.username, USERNAME_SIZE), ("Email", &self.email, EMAIL_SIZE)] {
+            let capacity = encoding.capacity(slot_size);
+            if value.len() > capacity {
+                return Err(DbError::InvalidRow(format!("{} is {} bytes long, at most {} bytes fit", name, value.len(), capacity)));
+            }
+        }
+        Ok(())
+    }
+
     pub fn from_string(s: &str) -> Result<Self, String> {
-        let words: Vec<&str> = s.split_whitespace().collect();
-        if words.len() != 3 {
-            return Err(format!("Expected 3 fields but got {} fields: {}", words.len(), s));
+        Row::from_tokens(&tokenize(s))
+    }
+
+    /// Parses the fields of a row naming the columns as in the default schema, see `from_tokens_with`
+    pub fn from_tokens(tokens: &[Token]) -> Result<Self, String> {
+        Row::from_tokens_with(tokens, &Schema::default())
+    }
+
+    /// Parses `<id> <username> <email>`, or the fields named like `email=a@b.com id=1 username=al` in any
+    /// order if the first token contains a `=`. Errors name the offending token and its position.
+    ///
+    /// Trailing control characters are stripped from the text fields, so stray bytes at the end of lines
+    /// of piped files, like the `\x1a` end-of-file marker of some Windows tools, do not end up in the row.
+    /// Line breaks, including the `\r` of CRLF line endings, are whitespace and never part of a token.
+    pub fn from_tokens_with(tokens: &[Token], schema: &Schema) -> Result<Self, String> {
+        if tokens.first().is_some_and(|token| token.text.contains('=')) {
+            return Row::from_named_tokens(tokens, schema);
+        }
+        match tokens {
+            [id, username, email] => {
+                Row::new(parse_id(id)?, text_value(username), text_value(email)).map_err(|e| e.to_string())
+            }
+            [_, _, _, extra, ..] => {
+                Err(format!("Expected 3 fields but got {} fields, unexpected {}; {}", tokens.len(), extra, INSERT_USAGE))
+            }
+            _ => {
+                let fields: Vec<&str> = tokens.iter().map(|token| token.text).collect();
+                Err(format!("Expected 3 fields but got {} fields: {}; {}", tokens.len(), fields.join(" "), INSERT_USAGE))
+            }
         }
-        let id = match words[0].parse::<u32>() {
-            Ok(val) => val,
-            Err(_) => return Err(String::from("Invalid id")),
-        };
+    }
 
-        let username = String::from(words[1]);
-        let email = String::from(words[2]);
-        Ok(Self { id, username, email })
+    fn from_named_tokens(tokens: &[Token], schema: &Schema) -> Result<Self, String> {
+        let mut values: [Option<Token>; 3] = [None; 3];
+        for token in tokens {
+            let (name, value) = token.text.split_once('=')
+                .ok_or_else(|| format!("Expected <column>=<value> but got {}", token))?;
+            let column = schema.column(name).ok_or_else(|| {
+                format!("Unknown column {}, valid columns are {}", token, schema.names().join(", "))
+            })?;
+            let slot = &mut values[COLUMNS.iter().position(|&c| c == column).unwrap()];
+            if slot.is_some() {
+                return Err(format!("Column {} is given more than once", schema.name(column)));
+            }
+            // the value is reported at its own position in errors
+            let skipped = name.len() + 1;
+            *slot = Some(Token { text: value, offset: token.offset + skipped, column: token.column + skipped, index: token.index });
+        }
+
+        match values {
+            [Some(id), Some(username), Some(email)] => {
+                Row::new(parse_id(&id)?, text_value(&username), text_value(&email)).map_err(|e| e.to_string())
+            }
+            _ => {
+                let missing: Vec<&str> = COLUMNS.iter().zip(&values)
+                    .filter(|(_, value)| value.is_none())
+                    .map(|(&column, _)| schema.name(column))
+                    .collect();
+                Err(format!("Missing value for {}", missing.join(", ")))
+            }
+        }
     }
 
-    pub fn serialize(&self) -> Vec<u8> {
+    /// Serializes the row with NUL-terminated strings, fields longer than their slot are truncated
+    pub fn serialize(&self) -> [u8; ROW_SIZE] {
+        self.serialize_with(StringEncoding::NulTerminated)
+    }
+
+    /// Serializes the row with the given string encoding, fields longer than their slot are truncated
+    pub fn serialize_with(&self, encoding: StringEncoding) -> [u8; ROW_SIZE] {
         let mut buf_array = [0; ROW_SIZE];
-        buf_array[..ID_SIZE].copy_from_slice(&self.id.to_le_bytes());
+        self.write_to(&mut buf_array, encoding);
+        buf_array
+    }
 
-        let username_size = cmp::min(USERNAME_SIZE, self.username.len());
-        buf_array[USERNAME_OFFSET..USERNAME_OFFSET + username_size].copy_from_slice(&self.username.as_bytes()[..username_size]);
+    /// Serializes the row with NUL-terminated strings into `buf`, see `serialize_into_with`
+    pub fn serialize_into(&self, buf: &mut [u8]) -> Result<(), DbError> {
+        self.serialize_into_with(buf, StringEncoding::NulTerminated)
+    }
 
-        let email_size = cmp::min(EMAIL_SIZE, self.email.len());
-        buf_array[EMAIL_OFFSET..EMAIL_OFFSET + email_size].copy_from_slice(&self.email.as_bytes()[..email_size]);
+    /// Serializes the row into `buf`, e.g. its slot in a page, which must be exactly `ROW_SIZE` bytes long.
+    /// Every byte is overwritten, so the buffer does not have to be zeroed. Fails instead of truncating a
+    /// field that does not fit its slot with `encoding`.
+    pub fn serialize_into_with(&self, buf: &mut [u8], encoding: StringEncoding) -> Result<(), DbError> {
+        self.check_fits(encoding)?;
+        let len = buf.len();
+        let buf: &mut [u8; ROW_SIZE] = buf.try_into()
+            .map_err(|_| DbError::InvalidRow(format!("Buffer of {} bytes does not fit a row of {} bytes", len, ROW_SIZE)))?;
+        buf.fill(0);
+        self.write_to(buf, encoding);
+        Ok(())
+    }
 
-        buf_array.to_vec()
+    fn write_to(&self, buf: &mut [u8; ROW_SIZE], encoding: StringEncoding) {
+        buf[..ID_SIZE].copy_from_slice(&self.id.to_le_bytes());
+        write_string(&mut buf[USERNAME_OFFSET..USERNAME_OFFSET + USERNAME_SIZE], &self.username, encoding);
+        write_string(&mut buf[EMAIL_OFFSET..EMAIL_OFFSET + EMAIL_SIZE], &self.email, encoding);
     }
 
     pub fn deserialize(bytes: &[u8]) -> Result<Self, String> {
+        RowRef::new(bytes)?.to_row()
+    }
+
+    pub fn deserialize_with(bytes: &[u8], encoding: StringEncoding) -> Result<Self, String> {
+        RowRef::with_encoding(bytes, encoding)?.to_row()
+    }
+}
+
+/// Serializes a row as a struct with the fields `id`, `username` and `email`, like a derived impl would
+#[cfg(feature = "serde")]
+impl serde::Serialize for Row {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        use serde::ser::SerializeStruct;
+
+        let mut state = serializer.serialize_struct("Row", 3)?;
+        state.serialize_field("id", &self.id)?;
+        state.serialize_field("username", &self.username)?;
+        state.serialize_field("email", &self.email)?;
+        state.end()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Row {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::{self, MapAccess, SeqAccess, Visitor};
+
+        const FIELDS: &[&str] = &["id", "username", "email"];
+
+        struct RowVisitor;
+
+        impl<'de> Visitor<'de> for RowVisitor {
+            type Value = Row;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a row with the fields id, username and email")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Row, A::Error> {
+                let id = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(0, &self))?;
+                let username: String = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(1, &self))?;
+                let email: String = seq.next_element()?.ok_or_else(|| de::Error::invalid_length(2, &self))?;
+                Row::new(id, username, email).map_err(de::Error::custom)
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Row, A::Error> {
+                let (mut id, mut username, mut email) = (None, None, None);
+                while let Some(key) = map.next_key::<String>()? {
+                    match key.as_str() {
+                        "id" if id.is_none() => id = Some(map.next_value()?),
+                        "username" if username.is_none() => username = Some(map.next_value()?),
+                        "email" if email.is_none() => email = Some(map.next_value()?),
+                        "id" | "username" | "email" => return Err(de::Error::custom(format!("duplicate field `{}`", key))),
+                        _ => return Err(de::Error::unknown_field(&key, FIELDS)),
+                    }
+                }
+                let id = id.ok_or_else(|| de::Error::missing_field("id"))?;
+                let username: String = username.ok_or_else(|| de::Error::missing_field("username"))?;
+                let email: String = email.ok_or_else(|| de::Error::missing_field("email"))?;
+                Row::new(id, username, email).map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_struct("Row", FIELDS, RowVisitor)
+    }
+}
+
+/// Parses the token as an id. The error tells an id that is too large apart from a negative id and input
+/// that is not an integer.
+pub fn parse_id(token: &Token) -> Result<u32, String> {
+    token.text.parse::<u32>().map_err(|e| match e.kind() {
+        IntErrorKind::PosOverflow => format!("Invalid id {}, id out of range, max is {}", token, u32::MAX),
+        _ if token.text.strip_prefix('-').is_some_and(|digits| !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit())) => {
+            format!("Invalid id {}, ids must not be negative", token)
+        }
+        _ => format!("Invalid id {}, id must be an integer", token),
+    })
+}
+
+/// A row that borrows its serialized bytes instead of copying them into `String`s.
+///
+/// The bytes usually live in a page of the table's storage, so a `RowRef` keeps the table borrowed
+/// for as long as it is alive. The text fields are only checked for valid UTF-8 when accessed.
+#[derive(Debug, Clone, Copy)]
+pub struct RowRef<'a> {
+    bytes: &'a [u8],
+    encoding: StringEncoding,
+}
+
+impl<'a> RowRef<'a> {
+    /// A view of a row with NUL-terminated strings
+    pub fn new(bytes: &'a [u8]) -> Result<Self, String> {
+        RowRef::with_encoding(bytes, StringEncoding::NulTerminated)
+    }
+
+    pub fn with_encoding(bytes: &'a [u8], encoding: StringEncoding) -> Result<Self, String> {
         if bytes.len() != ROW_SIZE {
             return Err(format!("Expected bytes array of size {} but got {}", ROW_SIZE, bytes.len()));
         }
+        Ok(RowRef { bytes, encoding })
+    }
 
+    pub fn id(&self) -> u32 {
         let mut id_bytes = [0; ID_SIZE];
-        id_bytes.copy_from_slice(&bytes[0..ID_SIZE]);
-        let id = u32::from_le_bytes(id_bytes);
+        id_bytes.copy_from_slice(&self.bytes[0..ID_SIZE]);
+        u32::from_le_bytes(id_bytes)
+    }
 
-        let username_bytes = &bytes[USERNAME_OFFSET..USERNAME_OFFSET + USERNAME_SIZE];
-        let username_end = get_nul_position(&username_bytes);
+    pub fn username(&self) -> Result<&'a str, String> {
+        read_string(&self.bytes[USERNAME_OFFSET..USERNAME_OFFSET + USERNAME_SIZE], self.encoding)
+    }
 
-        let username = std::str::from_utf8(&username_bytes[..username_end])
-            .map_err(|e| e.to_string())?.to_string();
+    pub fn email(&self) -> Result<&'a str, String> {
+        read_string(&self.bytes[EMAIL_OFFSET..EMAIL_OFFSET + EMAIL_SIZE], self.encoding)
+    }
 
-        let email_bytes = &bytes[EMAIL_OFFSET..EMAIL_OFFSET + EMAIL_SIZE];
-        let email_end = get_nul_position(&email_bytes);
+    pub fn to_row(&self) -> Result<Row, String> {
+        Ok(Row {
+            id: self.id(),
+            username: self.username()?.to_string(),
+            email: self.email()?.to_string(),
+        })
+    }
+}
 
-        let email = std::str::from_utf8(&email_bytes[..email_end])
-            .map_err(|e| e.to_string())?.to_string();
+impl fmt::Display for Row {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "({}, {}, {})", self.id, self.username, self.email)
+    }
+}
 
-        Ok(Row { id, username, email })
+fn text_value(token: &Token) -> String {
+    String::from(token.text.trim_end_matches(char::is_control))
+}
+
+/// Writes `s` to the slot, which must be zeroed, truncating it at a character boundary if it does not fit
+fn write_string(slot: &mut [u8], s: &str, encoding: StringEncoding) {
+    let mut len = cmp::min(encoding.capacity(slot.len()), s.len());
+    while !s.is_char_boundary(len) {
+        len -= 1;
+    }
+    let bytes = &s.as_bytes()[..len];
+    match encoding {
+        StringEncoding::NulTerminated => slot[..len].copy_from_slice(bytes),
+        StringEncoding::LengthPrefixed => {
+            slot[0] = len as u8;
+            slot[1..1 + len].copy_from_slice(bytes);
+        }
+    }
+}
+
+fn read_string(slot: &[u8], encoding: StringEncoding) -> Result<&str, String> {
+    match encoding {
+        StringEncoding::NulTerminated => decode_text_field(slot),
+        StringEncoding::LengthPrefixed => {
+            let len = slot[0] as usize;
+            let bytes = slot.get(1..1 + len)
+                .ok_or_else(|| format!("String length {} exceeds its slot of {} bytes", len, slot.len() - 1))?;
+            str::from_utf8(bytes)
+        }
     }
+    .map_err(|e| e.to_string())
 }
 
-fn get_nul_position(str_bytes: &[u8]) -> usize {
-    str_bytes.iter()
-        .position(|&c| c == b'\0')
-        .unwrap_or(str_bytes.len())
+/// Decodes a NUL-terminated text column, every row read from a page goes through it. A string filling
+/// the whole slot has no terminating NUL, the field then ends with the slot.
+pub fn decode_text_field(bytes: &[u8]) -> Result<&str, Utf8Error> {
+    let len = bytes.iter().position(|&b| b == b'\0').unwrap_or(bytes.len());
+    str::from_utf8(&bytes[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::SliceRandom;
+    use crate::error::DbError;
+    use crate::row::{decode_text_field, Row, StringEncoding, EMAIL_OFFSET, EMAIL_SIZE, ID_SIZE, ROW_SIZE, USERNAME_OFFSET, USERNAME_SIZE};
+
+    #[test]
+    fn new_validates_fields() -> Result<(), DbError> {
+        let row = Row::new(1, "al", "al@example.com")?;
+        assert_eq!(row, Row::from_string("1 al al@example.com").map_err(DbError::InvalidRow)?);
+        assert_eq!(Row::new(2, "x".repeat(USERNAME_SIZE), "a@b")?.username.len(), USERNAME_SIZE);
+
+        let err = Row::new(1, "x".repeat(USERNAME_SIZE + 1), "a@b").unwrap_err();
+        assert_eq!(err.to_string(), "Invalid row: Username is 33 bytes long, at most 32 bytes fit");
+        assert!(Row::new(1, "al", format!("a@{}", "x".repeat(EMAIL_SIZE))).is_err());
+        for email in ["", "al", "@example.com", "al@", "al@x@example.com"] {
+            assert!(matches!(Row::new(1, "al", email), Err(DbError::InvalidRow(_))), "{}", email);
+        }
+
+        // parsed rows are checked the same way
+        let err = Row::from_string(&format!("1 {} a@b", "x".repeat(USERNAME_SIZE + 1))).unwrap_err();
+        assert_eq!(err, "Invalid row: Username is 33 bytes long, at most 32 bytes fit");
+        assert!(Row::from_string("id=1 username=al email=al").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn display() -> Result<(), String> {
+        let row = Row::from_string("100 foo bar@baz.com")?;
+        assert_eq!(row.to_string(), "(100, foo, bar@baz.com)");
+        assert_eq!(format!("{:?}", row), r#"Row { id: 100, username: "foo", email: "bar@baz.com" }"#);
+
+        Ok(())
+    }
+
+    #[test]
+    fn strips_line_endings_and_control_chars() -> Result<(), String> {
+        let row = Row::from_string("1 foo foo@bar.com\r\n")?;
+        assert_eq!(row.email, "foo@bar.com");
+
+        let row = Row::from_string("1 foo\x07 foo@bar.com\x1a")?;
+        assert_eq!((row.username.as_str(), row.email.as_str()), ("foo", "foo@bar.com"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors_name_the_token() {
+        assert_eq!(
+            Row::from_string("abc foo foo@bar.com").unwrap_err(),
+            "Invalid id 'abc' at token 1, column 1, id must be an integer"
+        );
+        assert_eq!(
+            Row::from_string("-1 foo foo@bar.com").unwrap_err(),
+            "Invalid id '-1' at token 1, column 1, ids must not be negative"
+        );
+        assert_eq!(
+            Row::from_string("4294967296 foo foo@bar.com").unwrap_err(),
+            "Invalid id '4294967296' at token 1, column 1, id out of range, max is 4294967295"
+        );
+        assert!(Row::from_string("4294967295 foo foo@bar.com").is_ok());
+        assert!(Row::from_string("1.5 foo foo@bar.com").unwrap_err().ends_with("id must be an integer"));
+        assert!(Row::from_string("-99999999999 foo foo@bar.com").unwrap_err().ends_with("ids must not be negative"));
+        assert!(Row::from_string("- foo foo@bar.com").unwrap_err().ends_with("id must be an integer"));
+        assert_eq!(
+            Row::from_string("1 foo foo@bar.com  extra").unwrap_err(),
+            "Expected 3 fields but got 4 fields, unexpected 'extra' at token 4, column 20; usage: insert <id:int> <username> <email>"
+        );
+        assert_eq!(
+            Row::from_string("1 foo").unwrap_err(),
+            "Expected 3 fields but got 2 fields: 1 foo; usage: insert <id:int> <username> <email>"
+        );
+    }
+
+    #[test]
+    fn serialized_layout() -> Result<(), String> {
+        let bytes = Row::from_string("258 al al@example.com")?.serialize();
+        assert_eq!(bytes[..ID_SIZE], [2, 1, 0, 0]);
+        assert_eq!(bytes[USERNAME_OFFSET..USERNAME_OFFSET + 3], *b"al\0");
+        assert_eq!(bytes[EMAIL_OFFSET..EMAIL_OFFSET + 15], *b"al@example.com\0");
+        let text_bytes = [USERNAME_OFFSET + 2..EMAIL_OFFSET, EMAIL_OFFSET + 14..ROW_SIZE];
+        assert!(text_bytes.into_iter().flatten().all(|i| bytes[i] == 0));
+
+        let bytes = Row::from_string("1 al a@b")?.serialize_with(StringEncoding::LengthPrefixed);
+        assert_eq!(bytes[USERNAME_OFFSET..USERNAME_OFFSET + 4], [2, b'a', b'l', 0]);
+        assert_eq!(bytes[EMAIL_OFFSET..EMAIL_OFFSET + 5], [3, b'a', b'@', b'b', 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn serialize_into() -> Result<(), String> {
+        let row = Row::from_string("258 al al@example.com")?;
+        let mut page = vec![0xff; 2 * ROW_SIZE];
+        row.serialize_into(&mut page[ROW_SIZE..])?;
+        assert_eq!(page[ROW_SIZE..], row.serialize());
+        assert!(page[..ROW_SIZE].iter().all(|&b| b == 0xff));
+
+        row.serialize_into_with(&mut page[..ROW_SIZE], StringEncoding::LengthPrefixed)?;
+        assert_eq!(page[..ROW_SIZE], row.serialize_with(StringEncoding::LengthPrefixed));
+
+        assert!(row.serialize_into(&mut page[1..]).is_err());
+        assert!(row.serialize_into(&mut [0; ROW_SIZE - 1]).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn embedded_nul() -> Result<(), String> {
+        let row = Row::new(1, "foo\0bar", "\0@b.c")?;
+
+        let bytes = row.serialize_with(StringEncoding::LengthPrefixed);
+        assert_eq!(Row::deserialize_with(&bytes, StringEncoding::LengthPrefixed)?, row);
+
+        // NUL-terminated strings end at the first NUL byte
+        let truncated = Row::deserialize(&row.serialize())?;
+        assert_eq!(truncated.username, "foo");
+        assert_eq!(truncated.email, "");
+
+        Ok(())
+    }
+
+    #[test]
+    fn length_prefixed_truncates() -> Result<(), String> {
+        let row = Row::new(1, "x".repeat(USERNAME_SIZE), "a@b.c")?;
+
+        let bytes = row.serialize_with(StringEncoding::LengthPrefixed);
+        let read = Row::deserialize_with(&bytes, StringEncoding::LengthPrefixed)?;
+        assert_eq!(read.username, "x".repeat(USERNAME_SIZE - 1));
+
+        // a length that does not fit the slot is reported instead of reading into the next field
+        let mut bytes = bytes;
+        bytes[USERNAME_OFFSET] = USERNAME_SIZE as u8;
+        assert!(Row::deserialize_with(&bytes, StringEncoding::LengthPrefixed).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn text_fields() -> Result<(), String> {
+        let full = "x".repeat(USERNAME_SIZE);
+        assert_eq!(decode_text_field(full.as_bytes()), Ok(full.as_str()));
+        assert_eq!(decode_text_field(&[0; USERNAME_SIZE]), Ok(""));
+        assert_eq!(decode_text_field(b"al\0ignored\0"), Ok("al"));
+        assert!(decode_text_field(&[b'a', 0x80, 0]).is_err());
+
+        let row = Row::new(1, full.clone(), "a@b.c")?;
+        assert_eq!(Row::deserialize(&row.serialize())?, row);
+
+        Ok(())
+    }
+
+    #[test]
+    fn named_fields() -> Result<(), String> {
+        let row = Row::from_string("email=a@b.com id=1 USERNAME=bob")?;
+        assert_eq!(row, Row::from_string("1 bob a@b.com")?);
+
+        assert_eq!(Row::from_string("id=1 email=a@b.com").unwrap_err(), "Missing value for username");
+        assert_eq!(Row::from_string("id=1 username=bob email=a@b.com id=2").unwrap_err(), "Column id is given more than once");
+        assert_eq!(
+            Row::from_string("id=1 name=bob email=a@b.com").unwrap_err(),
+            "Unknown column 'name=bob' at token 2, column 6, valid columns are id, username, email"
+        );
+        assert_eq!(
+            Row::from_string("id=x username=bob email=a@b.com").unwrap_err(),
+            "Invalid id 'x' at token 1, column 4, id must be an integer"
+        );
+        assert!(Row::from_string("id=1 bob a@b.com").is_err());
+        // only the first token decides, a positional email may contain a `=`
+        assert_eq!(Row::from_string("1 bob a=b@c.com")?.email, "a=b@c.com");
+
+        Ok(())
+    }
+
+    #[test]
+    fn sorts_by_id_then_fields() -> Result<(), String> {
+        let mut rows = Vec::new();
+        for line in ["3 c c@x.io", "1 b b@x.io", "2 a a@x.io", "1 a z@x.io", "1 a a@x.io"] {
+            rows.push(Row::from_string(line)?);
+        }
+        rows.shuffle(&mut rand::thread_rng());
+        rows.sort();
+
+        let sorted: Vec<String> = rows.iter().map(Row::to_string).collect();
+        assert_eq!(sorted, ["(1, a, a@x.io)", "(1, a, z@x.io)", "(1, b, b@x.io)", "(2, a, a@x.io)", "(3, c, c@x.io)"]);
+
+        Ok(())
+    }
 }
@@ -0,0 +1,108 @@
+use crate::row::{ColumnName, COLUMNS, EMAIL_SIZE, ID_SIZE, USERNAME_SIZE};
+
+/// The names of the columns of a table.
+///
+/// The layout of a row is fixed, a schema only changes the names statements and output use for its
+/// columns, e.g. `contact` instead of `email`. Names are not stored in the database file.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schema {
+    // indexed like `COLUMNS`
+    names: [String; 3],
+}
+
+impl Default for Schema {
+    fn default() -> Self {
+        Schema { names: COLUMNS.map(|column| String::from(column.name())) }
+    }
+}
+
+impl Schema {
+    /// Renames a column. Names start with a letter followed by letters, digits or `_`, and must differ
+    /// from the names of the other columns, ignoring case.
+    pub fn rename(mut self, column: ColumnName, name: &str) -> Result<Self, String> {
+        let mut chars = name.chars();
+        let valid = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(format!("Invalid column name '{}'", name));
+        }
+        if let Some(other) = self.column(name).filter(|&other| other != column) {
+            return Err(format!("Column name '{}' is already used by column {}", name, other.name()));
+        }
+        self.names[index(column)] = String::from(name);
+        Ok(self)
+    }
+
+    pub fn name(&self, column: ColumnName) -> &str {
+        &self.names[index(column)]
+    }
+
+    /// The names of all columns in the order they are stored
+    pub fn names(&self) -> Vec<&str> {
+        self.names.iter().map(String::as_str).collect()
+    }
+
+    /// Looks up a column by name, ignoring case
+    pub fn column(&self, name: &str) -> Option<ColumnName> {
+        COLUMNS.into_iter().find(|&column| self.name(column).eq_ignore_ascii_case(name))
+    }
+
+    /// One line per column with its name, type and size in bytes, e.g. `username text(32)`
+    pub fn describe(&self) -> String {
+        COLUMNS.iter()
+            .map(|&column| {
+                let column_type = match column {
+                    ColumnName::Id => format!("integer({})", ID_SIZE),
+                    ColumnName::Username => format!("text({})", USERNAME_SIZE),
+                    ColumnName::Email => format!("text({})", EMAIL_SIZE),
+                };
+                format!("{} {}\n", self.name(column), column_type)
+            })
+            .collect()
+    }
+}
+
+fn index(column: ColumnName) -> usize {
+    COLUMNS.iter().position(|&c| c == column).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::output::{format_columns, OutputMode};
+    use crate::row::{ColumnName, Row};
+    use crate::schema::Schema;
+    use crate::statement::{parse_statement_with, Statement};
+    use crate::table::Table;
+
+    #[test]
+    fn renamed_columns() -> Result<(), String> {
+        let schema = Schema::default().rename(ColumnName::Email, "contact")?;
+        let mut table = Table::new().with_schema(schema);
+        table.insert_row(&Row::from_string("1 al al@example.com")?)?;
+
+        let columns = match parse_statement_with("select CONTACT, id", table.schema())? {
+            Statement::SelectColumns(columns) => columns,
+            statement => return Err(format!("Expected a projection, got {:?}", statement)),
+        };
+        assert_eq!(columns, [ColumnName::Email, ColumnName::Id]);
+        let rows: Vec<Row> = table.iter().collect();
+        let output = format_columns(&rows, &columns, &OutputMode::Table { max_width: 40 }, table.schema());
+        assert!(output.starts_with("contact        | id\n"));
+
+        assert_eq!(table.schema().describe(), "id integer(4)\nusername text(32)\ncontact text(255)\n");
+        assert_eq!(
+            parse_statement_with("select email", table.schema()).unwrap_err(),
+            "Unknown column 'email', valid columns are id, username, contact"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_names() {
+        assert!(Schema::default().rename(ColumnName::Email, "").is_err());
+        assert!(Schema::default().rename(ColumnName::Email, "e mail").is_err());
+        assert!(Schema::default().rename(ColumnName::Email, "Username").is_err());
+        assert!(Schema::default().rename(ColumnName::Email, "EMAIL").is_ok());
+    }
+}
@@ -0,0 +1,724 @@
+use std::fmt;
+use std::ops::RangeInclusive;
+
+use crate::row::{parse_id, ColumnName, Row, INSERT_USAGE};
+use crate::schema::Schema;
+use crate::token::{starts_with_keywords, tokenize, Token};
+
+const INSERT_CMD: &str = "insert";
+const SELECT_CMD: &str = "select";
+const DELETE_CMD: &str = "delete";
+const EXPLAIN_CMD: &str = "explain";
+const UPDATE_CMD: &str = "update";
+const CREATE_CMD: &str = "create";
+const DROP_CMD: &str = "drop";
+const INDEX_ON_KW: [&str; 2] = ["index", "on"];
+const WHERE_KW: &str = "where";
+const AND_KW: &str = "and";
+const OR_KW: &str = "or";
+const DISTINCT_KW: &str = "distinct";
+const GROUP_BY_KW: [&str; 2] = ["group", "by"];
+const COUNT_ALL: &str = "count(*)";
+const PHYSICAL_KW: &str = "physical";
+const FIRST_KW: &str = "first";
+const LAST_KW: &str = "last";
+const OR_REPLACE_KW: [&str; 2] = ["or", "replace"];
+
+#[derive(Debug)]
+pub enum Statement {
+    Insert(Row),
+    /// `insert or replace`, replaces the row with the same id if there is one
+    Upsert(Row),
+    /// `select` ordered by id, `select physical` in storage order, `select first` or `select last` for the
+    /// rows with the lowest or highest id, or `select <position>`
+    Select(String),
+    /// `select <column>, ...`, prints only the named columns
+    SelectColumns(Vec<ColumnName>),
+    SelectWhere(Condition),
+    /// `select distinct <column>`, prints every value of the column once in the order first seen
+    SelectDistinct(ColumnName),
+    /// `select <column>, count(*) group by <column>`, prints every value of a text column with the number
+    /// of rows holding it, ordered by value
+    CountGroupBy(ColumnName),
+    /// `select min(id)`, `select max(id)` or `select avg(id)`
+    Aggregate(AggKind),
+    Delete(Condition),
+    /// `create index on username`, indexes the rows by a column besides the id
+    CreateIndex(ColumnName),
+    /// `drop index on username`
+    DropIndex(ColumnName),
+    /// Describes how the statement would be executed instead of executing it
+    Explain(Box<Statement>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AggKind {
+    Min,
+    Max,
+    Avg,
+}
+
+/// The result of an aggregate, `min` and `max` keep the integer type of the id
+#[derive(Debug, PartialEq)]
+pub enum AggValue {
+    Int(u32),
+    Float(f64),
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Condition::UsernameLike(pattern) => write!(f, "username like '{}'", pattern),
+            Condition::UsernameEq(username) => write!(f, "username = '{}'", username),
+            Condition::Id(predicates) => {
+                let predicates: Vec<String> = predicates.iter().map(|predicate| format!("id{}", predicate)).collect();
+                write!(f, "{}", predicates.join(" and "))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Predicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.op, self.value)
+    }
+}
+
+impl fmt::Display for CompareOp {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let symbol = match self {
+            CompareOp::Eq => "=",
+            CompareOp::Ne => "!=",
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+        };
+        write!(f, "{}", symbol)
+    }
+}
+
+impl fmt::Display for AggKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggKind::Min => write!(f, "min(id)"),
+            AggKind::Max => write!(f, "max(id)"),
+            AggKind::Avg => write!(f, "avg(id)"),
+        }
+    }
+}
+
+impl AggKind {
+    /// Aggregates the ids, `None` if there are none
+    pub fn apply(&self, ids: impl Iterator<Item = u32>) -> Option<AggValue> {
+        match self {
+            AggKind::Min => ids.min().map(AggValue::Int),
+            AggKind::Max => ids.max().map(AggValue::Int),
+            AggKind::Avg => {
+                let (count, sum) = ids.fold((0u64, 0u64), |(count, sum), id| (count + 1, sum + id as u64));
+                (count > 0).then(|| AggValue::Float(sum as f64 / count as f64))
+            }
+        }
+    }
+}
+
+impl fmt::Display for AggValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AggValue::Int(value) => write!(f, "{}", value),
+            // always with a fractional part, e.g. 2.0
+            AggValue::Float(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+/// A filter on the rows of a select.
+///
+/// `like` patterns only support a single trailing `%` wildcard; a pattern without `%` must
+/// match exactly. Matching is case-sensitive and `_` has no special meaning.
+#[derive(Debug, PartialEq)]
+pub enum Condition {
+    UsernameLike(String),
+    /// `username = '<value>'`
+    UsernameEq(String),
+    /// `id <op> <value>`, e.g. `id >= 10`, or several of them joined by `and`, which must all hold
+    Id(Vec<Predicate>),
+}
+
+/// Compares a value to a constant
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Predicate {
+    pub op: CompareOp,
+    pub value: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl CompareOp {
+    /// Parses `=`, `!=`, `<`, `<=`, `>` or `>=`
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "=" => Some(CompareOp::Eq),
+            "!=" => Some(CompareOp::Ne),
+            "<" => Some(CompareOp::Lt),
+            "<=" => Some(CompareOp::Le),
+            ">" => Some(CompareOp::Gt),
+            ">=" => Some(CompareOp::Ge),
+            _ => None,
+        }
+    }
+}
+
+impl Predicate {
+    pub fn matches(&self, value: u32) -> bool {
+        match self.op {
+            CompareOp::Eq => value == self.value,
+            CompareOp::Ne => value != self.value,
+            CompareOp::Lt => value < self.value,
+            CompareOp::Le => value <= self.value,
+            CompareOp::Gt => value > self.value,
+            CompareOp::Ge => value >= self.value,
+        }
+    }
+
+    /// The values matching the predicate as a single range, which is empty if none do. `None` for `!=`.
+    pub fn range(&self) -> Option<RangeInclusive<u32>> {
+        let value = self.value;
+        match self.op {
+            CompareOp::Eq => Some(value..=value),
+            CompareOp::Ne => None,
+            CompareOp::Lt => Some(value.checked_sub(1).map_or(empty_range(), |end| 0..=end)),
+            CompareOp::Le => Some(0..=value),
+            CompareOp::Gt => Some(value.checked_add(1).map_or(empty_range(), |start| start..=u32::MAX)),
+            CompareOp::Ge => Some(value..=u32::MAX),
+        }
+    }
+}
+
+fn empty_range() -> RangeInclusive<u32> {
+    RangeInclusive::new(1, 0)
+}
+
+impl Condition {
+    pub fn matches(&self, row: &Row) -> bool {
+        match self {
+            Condition::UsernameLike(pattern) => match pattern.strip_suffix('%') {
+                Some(prefix) => row.username.starts_with(prefix),
+                None => row.username == *pattern,
+            },
+            Condition::UsernameEq(username) => row.username == *username,
+            Condition::Id(predicates) => predicates.iter().all(|predicate| predicate.matches(row.id)),
+        }
+    }
+
+    /// The id a condition `id = <value>` looks up
+    pub fn id_equals(&self) -> Option<u32> {
+        match self {
+            Condition::Id(predicates) => match predicates.as_slice() {
+                [Predicate { op: CompareOp::Eq, value }] => Some(*value),
+                _ => None,
+            },
+            Condition::UsernameLike(_) | Condition::UsernameEq(_) => None,
+        }
+    }
+
+    /// The username a condition matches exactly: `username = '<value>'`, or a `like` pattern without a
+    /// wildcard
+    pub fn username_equals(&self) -> Option<&str> {
+        match self {
+            Condition::UsernameEq(username) => Some(username),
+            Condition::UsernameLike(pattern) if !pattern.ends_with('%') => Some(pattern),
+            _ => None,
+        }
+    }
+
+    /// The ids matching a condition on ids as a single range, which is empty if none do. `None` if the
+    /// condition is not on ids or uses `!=`.
+    pub fn id_range(&self) -> Option<RangeInclusive<u32>> {
+        match self {
+            Condition::Id(predicates) => predicates.iter().try_fold(0..=u32::MAX, |range, predicate| {
+                let other = predicate.range()?;
+                Some(*range.start().max(other.start())..=*range.end().min(other.end()))
+            }),
+            Condition::UsernameLike(_) | Condition::UsernameEq(_) => None,
+        }
+    }
+}
+
+/// Parses a statement naming the columns as in the default schema
+pub fn parse_statement(s: &str) -> Result<Statement, String> {
+    parse_statement_with(s, &Schema::default())
+}
+
+/// Parses a statement naming the columns as in `schema`
+pub fn parse_statement_with(s: &str, schema: &Schema) -> Result<Statement, String> {
+    let tokens = tokenize(s);
+    let command = match tokens.first() {
+        Some(command) => command,
+        None => return Err("Unknown statement".to_string()),
+    };
+    match command.text.to_lowercase().as_str() {
+        INSERT_CMD if tokens.len() == 1 => Err(format!("Illegal insert statement: missing arguments, {}", INSERT_USAGE)),
+        INSERT_CMD => {
+            if starts_with_keywords(&tokens[1..], &OR_REPLACE_KW) {
+                match Row::from_tokens_with(&tokens[1 + OR_REPLACE_KW.len()..], schema) {
+                    Ok(row) => Ok(Statement::Upsert(row)),
+                    Err(e) => Err(format!("Illegal insert or replace statement: {}", e))
+                }
+            } else {
+                match Row::from_tokens_with(&tokens[1..], schema) {
+                    Ok(row) => Ok(Statement::Insert(row)),
+                    Err(e) => Err(format!("Illegal insert statement: {}", e))
+                }
+            }
+        }
+        SELECT_CMD => {
+            let args = tokens.get(1).map_or("", |token| s[token.offset..].trim_end());
+            if let Some(kind) = parse_aggregate(args) {
+                Ok(Statement::Aggregate(kind))
+            } else if let Some(group_by) = (1..tokens.len()).find(|&i| starts_with_keywords(&tokens[i..], &GROUP_BY_KW)) {
+                let columns = &s[tokens[1].offset..tokens[group_by].offset];
+                let group_column = tokens.get(group_by + GROUP_BY_KW.len()).map_or("", |token| s[token.offset..].trim_end());
+                parse_group_by(columns, group_column, schema)
+            } else if starts_with_keywords(&tokens[1..], &[WHERE_KW]) {
+                let condition = parse_condition(&tokens[2..], schema)?;
+                Ok(Statement::SelectWhere(condition))
+            } else if starts_with_keywords(&tokens[1..], &[DISTINCT_KW]) {
+                let columns = match tokens.get(2) {
+                    Some(token) => parse_columns(s[token.offset..].trim_end(), schema)?,
+                    None => return Err(String::from("Illegal select distinct statement: expected select distinct <column>")),
+                };
+                match columns.as_slice() {
+                    &[column] => Ok(Statement::SelectDistinct(column)),
+                    _ => Err(String::from("Illegal select distinct statement: only a single column is supported")),
+                }
+            } else if args.is_empty()
+                || [PHYSICAL_KW, FIRST_KW, LAST_KW].iter().any(|keyword| args.eq_ignore_ascii_case(keyword))
+                || args.parse::<usize>().is_ok()
+            {
+                Ok(Statement::Select(String::from(args)))
+            } else {
+                Ok(Statement::SelectColumns(parse_columns(args, schema)?))
+            }
+        }
+        DELETE_CMD if tokens.len() == 1 => {
+            Err(String::from("Illegal delete statement: missing arguments, expected delete where <condition>"))
+        }
+        DELETE_CMD => {
+            if starts_with_keywords(&tokens[1..], &[WHERE_KW]) {
+                Ok(Statement::Delete(parse_condition(&tokens[2..], schema)?))
+            } else {
+                Err(String::from("Illegal delete statement: expected delete where <condition>"))
+            }
+        }
+        EXPLAIN_CMD => match tokens.get(1) {
+            Some(token) => Ok(Statement::Explain(Box::new(parse_statement_with(&s[token.offset..], schema)?))),
+            None => Err(String::from("Illegal explain statement: expected explain <statement>")),
+        },
+        CREATE_CMD | DROP_CMD => {
+            let create = command.text.eq_ignore_ascii_case(CREATE_CMD);
+            let usage = format!("expected {} index on <column>", command.text.to_lowercase());
+            let column = match tokens.get(1 + INDEX_ON_KW.len()) {
+                Some(token) if starts_with_keywords(&tokens[1..], &INDEX_ON_KW) => parse_columns(s[token.offset..].trim_end(), schema)?,
+                _ => return Err(format!("Illegal statement: {}", usage)),
+            };
+            match *column.as_slice() {
+                [ColumnName::Username] if create => Ok(Statement::CreateIndex(ColumnName::Username)),
+                [ColumnName::Username] => Ok(Statement::DropIndex(ColumnName::Username)),
+                [column] => {
+                    let username = schema.name(ColumnName::Username);
+                    Err(format!("Unsupported index on {}, only {} can be indexed, the id always is", schema.name(column), username))
+                }
+                _ => Err(format!("Illegal statement: {}", usage)),
+            }
+        }
+        // rows are changed by replacing them, there is no update statement
+        UPDATE_CMD => Err(format!("Unsupported statement {}, use insert or replace <id> <username> <email>", command)),
+        _ => Err(format!("Unknown statement {}", command)),
+    }
+}
+
+/// Parses `min(id)`, `max(id)` or `avg(id)`, ignoring case and whitespace
+fn parse_aggregate(s: &str) -> Option<AggKind> {
+    let s: String = s.split_whitespace().collect::<String>().to_lowercase();
+    match s.as_str() {
+        "min(id)" => Some(AggKind::Min),
+        "max(id)" => Some(AggKind::Max),
+        "avg(id)" => Some(AggKind::Avg),
+        _ => None,
+    }
+}
+
+/// Parses the columns and the grouping column of `select <column>, count(*) group by <column>`
+fn parse_group_by(columns: &str, group_column: &str, schema: &Schema) -> Result<Statement, String> {
+    const USAGE: &str = "expected select <column>, count(*) group by <column>";
+    let column = match parse_columns(group_column, schema)?.as_slice() {
+        &[column] => column,
+        _ => return Err(String::from("Illegal group by: only a single column is supported")),
+    };
+    if column == ColumnName::Id {
+        return Err(format!("Illegal group by: only text columns can be grouped, not {}", schema.name(column)));
+    }
+    match columns.split(',').map(str::trim).collect::<Vec<&str>>().as_slice() {
+        [selected, count] if schema.column(selected) == Some(column)
+            && count.split_whitespace().collect::<String>().eq_ignore_ascii_case(COUNT_ALL) => {
+            Ok(Statement::CountGroupBy(column))
+        }
+        _ => Err(format!("Illegal group by: {}", USAGE)),
+    }
+}
+
+/// Parses a comma-separated list of column names
+fn parse_columns(s: &str, schema: &Schema) -> Result<Vec<ColumnName>, String> {
+    s.split(',')
+        .map(|name| {
+            schema.column(name.trim()).ok_or_else(|| {
+                format!("Unknown column '{}', valid columns are {}", name.trim(), schema.names().join(", "))
+            })
+        })
+        .collect()
+}
+
+/// Parses a single condition or comparisons of the id joined by `and`
+fn parse_condition(tokens: &[Token], schema: &Schema) -> Result<Condition, String> {
+    if tokens.is_empty() {
+        return Err(String::from("Missing condition after where"));
+    }
+    if let Some(or) = tokens.iter().find(|token| token.text.eq_ignore_ascii_case(OR_KW)) {
+        return Err(format!("Unsupported {}, conditions can only be combined with and", or));
+    }
+    let parts: Vec<&[Token]> = tokens.split(|token| token.text.eq_ignore_ascii_case(AND_KW)).collect();
+    if parts.len() == 1 {
+        return parse_single_condition(tokens, schema);
+    }
+
+    let mut predicates = Vec::new();
+    for part in parts {
+        match parse_single_condition(part, schema)? {
+            Condition::Id(part_predicates) => predicates.extend(part_predicates),
+            condition => return Err(format!("Unsupported and of {}, only comparisons of the id can be combined", condition)),
+        }
+    }
+    Ok(Condition::Id(predicates))
+}
+
+fn parse_single_condition(tokens: &[Token], schema: &Schema) -> Result<Condition, String> {
+    let words: Vec<&str> = tokens.iter().map(|token| token.text).collect();
+    match words.as_slice() {
+        [column, op, _] if schema.column(column) == Some(ColumnName::Username) && op.eq_ignore_ascii_case("like") => {
+            let pattern = unquote(&tokens[2])?;
+            let prefix = pattern.strip_suffix('%').unwrap_or(pattern);
+            if prefix.contains('%') {
+                return Err(format!("Unsupported like pattern {}: only a trailing % wildcard is supported", tokens[2]));
+            }
+            Ok(Condition::UsernameLike(String::from(pattern)))
+        }
+        [column, "=", _] if schema.column(column) == Some(ColumnName::Username) => {
+            Ok(Condition::UsernameEq(String::from(unquote(&tokens[2])?)))
+        }
+        [column, op, _] if schema.column(column) == Some(ColumnName::Id) && CompareOp::parse(op).is_some() => {
+            let op = CompareOp::parse(op).unwrap();
+            Ok(Condition::Id(vec![Predicate { op, value: parse_id(&tokens[2])? }]))
+        }
+        _ => Err(format!("Unsupported where clause: '{}'", words.join(" "))),
+    }
+}
+
+/// Removes a `--` comment running to the end of the line. A `--` inside a quoted string is kept.
+pub fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut prev = None;
+    for (i, c) in line.char_indices() {
+        match c {
+            '\'' => quoted = !quoted,
+            '-' if !quoted && prev == Some('-') => return &line[..i - 1],
+            _ => {}
+        }
+        prev = Some(c);
+    }
+    line
+}
+
+fn unquote<'a>(token: &Token<'a>) -> Result<&'a str, String> {
+    token.text.strip_prefix('\'')
+        .and_then(|s| s.strip_suffix('\''))
+        .ok_or_else(|| format!("Expected a quoted string but got {}", token))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::row::{ColumnName, Row};
+    use crate::statement::{AggKind, AggValue, CompareOp, Condition, parse_statement, Predicate, Statement, strip_comment};
+    use crate::table::Table;
+
+    fn parse_condition(s: &str) -> Result<Condition, String> {
+        match parse_statement(s)? {
+            Statement::SelectWhere(condition) => Ok(condition),
+            other => Err(format!("Expected a select with where clause but got {:?}", other)),
+        }
+    }
+
+    #[test]
+    fn parse_like() -> Result<(), String> {
+        assert_eq!(parse_condition("select where username like 'jo%'")?, Condition::UsernameLike("jo%".to_string()));
+        assert_eq!(parse_condition("SELECT WHERE username LIKE 'Jo'")?, Condition::UsernameLike("Jo".to_string()));
+
+        assert!(parse_statement("select where username like '%jo'").is_err());
+        assert!(parse_statement("select where username like 'j%o'").is_err());
+        assert!(parse_statement("select where username like jo%").is_err());
+        assert!(parse_statement("select where email like 'jo%'").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_id_equals() -> Result<(), String> {
+        let equals = |value| Condition::Id(vec![Predicate { op: CompareOp::Eq, value }]);
+        assert_eq!(parse_condition("select where id = 7")?, equals(7));
+        assert_eq!(parse_condition("select WHERE ID = 7")?, equals(7));
+        assert!(parse_statement("select where id = x").is_err());
+        assert!(parse_statement("select where id => 7").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn id_comparisons() -> Result<(), String> {
+        let mut table = Table::new();
+        for id in [0, 5, 9, 10, 11, u32::MAX] {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
+        }
+        let ids = |s: &str| -> Result<Vec<u32>, String> {
+            let condition = parse_condition(s)?;
+            Ok(table.iter().filter(|row| condition.matches(row)).map(|row| row.id).collect())
+        };
+
+        assert_eq!(ids("select where id > 10")?, [11, u32::MAX]);
+        assert_eq!(ids("select where id >= 10")?, [10, 11, u32::MAX]);
+        assert_eq!(ids("select where id < 10")?, [0, 5, 9]);
+        assert_eq!(ids("select where id <= 10")?, [0, 5, 9, 10]);
+        assert_eq!(ids("select where id != 10")?, [0, 5, 9, 11, u32::MAX]);
+        assert_eq!(ids("select where id < 0")?, []);
+        assert_eq!(ids("select where id >= 4294967295")?, [u32::MAX]);
+        assert_eq!(ids("select where id > 4294967295")?, []);
+        assert_eq!(parse_condition("select where id != 3")?.to_string(), "id!=3");
+
+        Ok(())
+    }
+
+    #[test]
+    fn id_conjunctions() -> Result<(), String> {
+        let mut table = Table::new();
+        for id in 5..25 {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
+        }
+        let ids = |s: &str| -> Result<Vec<u32>, String> {
+            let condition = parse_condition(s)?;
+            Ok(table.iter().filter(|row| condition.matches(row)).map(|row| row.id).collect())
+        };
+
+        let condition = parse_condition("select where id > 10 AND id < 20 and id != 15")?;
+        assert_eq!(condition.to_string(), "id>10 and id<20 and id!=15");
+        assert_eq!(condition.id_range(), None);
+        assert_eq!(ids("select where id > 10 and id < 20 and id != 15")?, [11, 12, 13, 14, 16, 17, 18, 19]);
+        assert_eq!(parse_condition("select where id > 10 and id <= 20")?.id_range(), Some(11..=20));
+
+        // a contradictory range matches nothing
+        assert_eq!(ids("select where id > 20 and id < 10")?, []);
+        assert!(parse_condition("select where id > 20 and id < 10")?.id_range().unwrap().is_empty());
+        assert!(parse_condition("select where id < 0")?.id_range().unwrap().is_empty());
+
+        assert!(parse_statement("select where id > 10 or id < 5").unwrap_err().contains("'or'"));
+        assert!(parse_statement("select where id > 10 and username like 'a%'").is_err());
+        assert!(parse_statement("select where id > 10 and").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_upsert() -> Result<(), String> {
+        match parse_statement("insert or replace 1 foo foo@bar.com")? {
+            Statement::Upsert(row) => assert_eq!(row, Row::from_string("1 foo foo@bar.com")?),
+            other => return Err(format!("Expected an upsert but got {:?}", other)),
+        }
+        assert!(matches!(parse_statement("INSERT OR REPLACE 1 foo foo@bar.com")?, Statement::Upsert(_)));
+        assert!(matches!(parse_statement("insert 1 foo foo@bar.com")?, Statement::Insert(_)));
+        assert!(parse_statement("insert or replace 1 foo").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn aggregates() -> Result<(), String> {
+        let mut table = Table::new();
+        let aggregate = |table: &Table, s: &str| match parse_statement(s) {
+            Ok(Statement::Aggregate(kind)) => Ok(kind.apply(table.iter().map(|row| row.id))),
+            other => Err(format!("Expected an aggregate but got {:?}", other)),
+        };
+
+        // aggregates over no rows are NULL
+        assert_eq!(aggregate(&table, "select avg(id)")?, None);
+        assert_eq!(aggregate(&table, "select max(id)")?, None);
+
+        for id in [4, 1, 10, 3] {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
+        }
+        assert_eq!(aggregate(&table, "select min(id)")?, Some(AggValue::Int(1)));
+        assert_eq!(aggregate(&table, "SELECT MAX( id )")?, Some(AggValue::Int(10)));
+        assert_eq!(aggregate(&table, "select avg(id)")?, Some(AggValue::Float(4.5)));
+        assert_eq!(AggKind::Avg.apply([2, 2].into_iter()).unwrap().to_string(), "2.0");
+        assert!(parse_statement("select sum(id)").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_delete() -> Result<(), String> {
+        assert!(matches!(
+            parse_statement("delete where id = 3")?,
+            Statement::Delete(Condition::Id(predicates)) if predicates == [Predicate { op: CompareOp::Eq, value: 3 }]
+        ));
+        assert!(matches!(parse_statement("DELETE WHERE username like 'a%'")?, Statement::Delete(Condition::UsernameLike(_))));
+        assert!(parse_statement("delete").is_err());
+        assert!(parse_statement("delete 3").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_errors_name_the_token() {
+        let error = parse_statement("insert abc foo foo@bar.com").unwrap_err();
+        assert_eq!(
+            error,
+            "Illegal insert statement: Invalid id 'abc' at token 2, column 8, id must be an integer"
+        );
+        assert!(parse_statement("select where id = x7").unwrap_err().contains("'x7' at token 5, column 19"));
+        assert!(parse_statement("insert -1 a a@b.com").unwrap_err().contains("ids must not be negative"));
+        assert!(parse_statement("delete where id = -1").unwrap_err().contains("ids must not be negative"));
+        assert!(parse_statement("insert 4294967296 a a@b.com").unwrap_err().contains("id out of range, max is 4294967295"));
+        assert!(parse_statement("select where username like jo%").unwrap_err().contains("'jo%' at token 5"));
+        assert_eq!(parse_statement("  frobnicate 1").unwrap_err(), "Unknown statement 'frobnicate' at token 1, column 3");
+    }
+
+    #[test]
+    fn bare_keywords() {
+        assert_eq!(
+            parse_statement("insert").unwrap_err(),
+            "Illegal insert statement: missing arguments, usage: insert <id:int> <username> <email>"
+        );
+        assert_eq!(
+            parse_statement(" DELETE ").unwrap_err(),
+            "Illegal delete statement: missing arguments, expected delete where <condition>"
+        );
+        assert_eq!(parse_statement("delete where").unwrap_err(), "Missing condition after where");
+        assert_eq!(parse_statement("select where").unwrap_err(), "Missing condition after where");
+        assert!(parse_statement("update").unwrap_err().starts_with("Unsupported statement 'update' at token 1, column 1"));
+    }
+
+    #[test]
+    fn parse_columns() -> Result<(), String> {
+        match parse_statement("select id, EMAIL")? {
+            Statement::SelectColumns(columns) => assert_eq!(columns, [ColumnName::Id, ColumnName::Email]),
+            other => return Err(format!("Expected a projection but got {:?}", other)),
+        }
+        assert!(matches!(parse_statement("select 3")?, Statement::Select(_)));
+        assert_eq!(
+            parse_statement("select id, name").unwrap_err(),
+            "Unknown column 'name', valid columns are id, username, email"
+        );
+        assert!(parse_statement("select id,").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_distinct() -> Result<(), String> {
+        assert!(matches!(parse_statement("select distinct username")?, Statement::SelectDistinct(ColumnName::Username)));
+        assert!(matches!(parse_statement("SELECT DISTINCT Email")?, Statement::SelectDistinct(ColumnName::Email)));
+        assert!(parse_statement("select distinct").is_err());
+        assert!(parse_statement("select distinct id, username").unwrap_err().contains("only a single column"));
+        assert!(parse_statement("select distinct name").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_index() -> Result<(), String> {
+        assert!(matches!(parse_statement("create index on username")?, Statement::CreateIndex(ColumnName::Username)));
+        assert!(matches!(parse_statement("DROP INDEX ON Username")?, Statement::DropIndex(ColumnName::Username)));
+        assert!(parse_statement("create index on id").unwrap_err().contains("only username can be indexed"));
+        assert!(parse_statement("create index on name").is_err());
+        assert!(parse_statement("create index username").is_err());
+        assert!(parse_statement("drop index on").is_err());
+
+        match parse_statement("select where username = 'al'")? {
+            Statement::SelectWhere(condition) => {
+                assert_eq!(condition, Condition::UsernameEq(String::from("al")));
+                assert_eq!(condition.username_equals(), Some("al"));
+            }
+            statement => return Err(format!("Unexpected {:?}", statement)),
+        }
+        assert!(parse_statement("select where username = al").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn parse_group_by() -> Result<(), String> {
+        assert!(matches!(parse_statement("select username, count(*) group by username")?, Statement::CountGroupBy(ColumnName::Username)));
+        assert!(matches!(parse_statement("SELECT Email, COUNT( * ) GROUP BY email")?, Statement::CountGroupBy(ColumnName::Email)));
+        assert!(parse_statement("select username group by username").is_err());
+        assert!(parse_statement("select username, count(*) group by").is_err());
+        assert!(parse_statement("select email, count(*) group by username").is_err());
+        assert!(parse_statement("select id, count(*) group by id").unwrap_err().contains("only text columns"));
+        assert!(parse_statement("select username, count(*) group by username, email").unwrap_err().contains("single column"));
+
+        Ok(())
+    }
+
+    #[test]
+    fn strips_comments() {
+        assert_eq!(strip_comment("-- seed data"), "");
+        assert_eq!(strip_comment("insert 1 a a@b.c -- first user"), "insert 1 a a@b.c ");
+        assert_eq!(strip_comment("insert 1 a-b a@b.c"), "insert 1 a-b a@b.c");
+        assert_eq!(strip_comment("select where username like '--%' -- dashes"), "select where username like '--%' ");
+    }
+
+    #[test]
+    fn like_matches_prefix() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::from_string("1 john john@example.com")?)?;
+        table.insert_row(&Row::from_string("2 mary mary@example.com")?)?;
+        table.insert_row(&Row::from_string("3 joanna joanna@example.com")?)?;
+        table.insert_row(&Row::from_string("4 Jo jo@example.com")?)?;
+
+        let condition = parse_condition("select where username like 'jo%'")?;
+        let ids: Vec<u32> = table.iter().filter(|row| condition.matches(row)).map(|row| row.id).collect();
+        assert_eq!(ids, vec![1, 3]);
+
+        let condition = parse_condition("select where username like 'mary'")?;
+        let ids: Vec<u32> = table.iter().filter(|row| condition.matches(row)).map(|row| row.id).collect();
+        assert_eq!(ids, vec![2]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn like_matches_nothing() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::from_string("1 john john@example.com")?)?;
+
+        let condition = parse_condition("select where username like 'x%'")?;
+        assert_eq!(table.iter().filter(|row| condition.matches(row)).count(), 0);
+
+        Ok(())
+    }
+}
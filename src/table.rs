@@ -1,255 +1,2267 @@
-use crate::row::{Row, ROW_SIZE};
+use std::cmp;
+use std::collections::{BTreeMap, HashSet};
+use std::fs::{self, File};
+use std::ops::{Range, RangeBounds};
+use std::path::{Path, PathBuf};
+
+use crate::error::DbError;
+use crate::header::{Header, HEADER_SIZE};
+use crate::config::Config;
+use crate::pager::{check_page_size, FileStorage, MemoryStorage, OnTruncated, Storage, PAGE_DATA_SIZE, PAGE_SIZE};
+use crate::row::{ColumnName, Row, RowRef, StringEncoding, ROW_SIZE};
+use crate::schema::Schema;
 
-const PAGE_SIZE: usize = 4096;
 const TABLE_MAX_PAGES: usize = 100;
-const ROWS_PER_PAGE: usize = PAGE_SIZE / ROW_SIZE;
+/// Number of rows a page of the default size holds
+pub const ROWS_PER_PAGE: usize = PAGE_DATA_SIZE / ROW_SIZE;
+
+#[derive(Debug)]
+pub struct Table {
+    storage: Box<dyn Storage>,
+    num_rows: usize,
+    max_pages: usize,
+    encoding: StringEncoding,
+    // positions of the rows by id, ids are not unique
+    index: BTreeMap<u32, Vec<usize>>,
+    // positions of the rows by username once `create_username_index` was called, kept in memory only
+    username_index: Option<BTreeMap<String, Vec<usize>>>,
+    // reject rows with id 0, which is kept free as a "no row" marker
+    reserve_zero_id: bool,
+    schema: Schema,
+    // reject every change with `DbError::ReadOnly` and never write to the file
+    read_only: bool,
+    // rows changed since the last flush, which dropping the table writes to its file
+    modified: bool,
+}
+
+/// Where the next inserted row is stored, see `Table::next_placement`
+#[derive(Debug, PartialEq)]
+pub struct Placement {
+    pub page_num: usize,
+    pub byte_offset: usize,
+    /// The row does not fit in the last page, a new page is allocated for it
+    pub new_page: bool,
+}
+
+/// The outcome of `Table::upsert_row`
+#[derive(Debug, PartialEq)]
+pub enum Upsert {
+    Inserted(usize),
+    Replaced(usize),
+}
+
+/// What `Table::merge` does with a row whose id is already taken
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OnDuplicate {
+    /// Leave the row out
+    Skip,
+    /// Fail the merge with `DuplicateId` before inserting anything
+    Error,
+}
+
+/// How the bytes of a table's file are used, see `Table::disk_usage`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DiskUsage {
+    /// Size of the file after the next flush: the header and all pages
+    pub total_bytes: usize,
+    /// Bytes holding rows
+    pub live_bytes: usize,
+    /// Bytes of the pages holding no row, the free slots of the last page and the space at the end of
+    /// every page that is too small for a row. Checksums and the header count as neither.
+    pub dead_bytes: usize,
+}
+
+impl DiskUsage {
+    /// Percentage of the row space of the pages that holds no row
+    pub fn fragmentation(&self) -> f64 {
+        match self.live_bytes + self.dead_bytes {
+            0 => 0.0,
+            row_space => self.dead_bytes as f64 * 100.0 / row_space as f64,
+        }
+    }
+}
+
+/// The number of rows `Table::merge` inserted and left out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MergeSummary {
+    pub inserted: usize,
+    pub skipped: usize,
+}
+
+impl Default for Table {
+    fn default() -> Self {
+        Table::new()
+    }
+}
+
+impl Table {
+    /// Creates an empty in-memory table
+    pub fn new() -> Self {
+        Table {
+            storage: Box::new(MemoryStorage::new(PAGE_SIZE)),
+            num_rows: 0,
+            max_pages: TABLE_MAX_PAGES,
+            encoding: StringEncoding::default(),
+            index: BTreeMap::new(),
+            username_index: None,
+            reserve_zero_id: false,
+            schema: Schema::default(),
+            read_only: false,
+            modified: false,
+        }
+    }
+
+    /// Opens the table stored in the file at `path`, creating an empty file if it does not exist
+    pub fn open(path: &Path) -> Result<Self, DbError> {
+        Ok(Table::with_storage(Box::new(FileStorage::open(path, None)?)))
+    }
+
+    /// Creates the table described by `config`: the table in its file, or an empty in-memory table if
+    /// it has no path. With `recover` set a truncated file loses its incomplete last page, see
+    /// `dropped_bytes`.
+    pub fn with_config(config: &Config) -> Result<Self, DbError> {
+        let mut builder = Table::builder()
+            .on_truncated(if config.recover { OnTruncated::Drop } else { OnTruncated::Error })
+            .read_only(config.read_only);
+        if let Some(path) = &config.path {
+            builder = builder.path(path);
+        }
+        if let Some(page_size) = config.page_size {
+            builder = builder.page_size(page_size);
+        }
+        if let Some(cache_size) = config.cache_size {
+            builder = builder.cache_size(cache_size);
+        }
+        builder.build()
+    }
+
+    /// Opens the table stored in the existing file at `path` for reading only. Every change fails with
+    /// `DbError::ReadOnly` and the file is never written to, see `FileStorage::open_read_only`.
+    pub fn open_readonly(path: &Path) -> Result<Self, DbError> {
+        Table::builder().path(path).read_only(true).build()
+    }
+
+    /// Starts configuring a table, see `TableBuilder`
+    pub fn builder() -> TableBuilder {
+        TableBuilder::default()
+    }
+
+    /// Serializes the whole table, header and pages, into a self-contained image in the file format.
+    /// Unlike `flush` this leaves the backing file alone.
+    pub fn snapshot(&self) -> Vec<u8> {
+        self.storage.image(self.header())
+    }
+
+    /// Reconstructs an in-memory table from an image taken with `snapshot`
+    pub fn restore(blob: &[u8]) -> Result<Table, DbError> {
+        Ok(Table::with_storage(Box::new(MemoryStorage::from_image(blob)?)))
+    }
+
+    /// Writes the rows ordered by id to a new file at `path`, packed into as few pages as they fit, and
+    /// returns how many were written. The copy keeps the page size and string encoding, this table and its
+    /// file are left alone. Fails if `path` exists, a copy that could not be completed is removed.
+    pub fn compact_into(&self, path: &Path) -> Result<usize, DbError> {
+        File::create_new(path)?;
+        let copy = || -> Result<usize, DbError> {
+            let mut compacted = Table::builder().path(path).page_size(self.page_size()).max_pages(self.max_pages).build()?;
+            compacted.set_encoding(self.encoding)?;
+            let positions: Vec<usize> = self.positions_by_id().collect();
+            // a page of rows at a time
+            for chunk in positions.chunks(self.rows_per_page()) {
+                let rows = chunk.iter()
+                    .filter_map(|&position| self.select_row(position).transpose())
+                    .collect::<Result<Vec<Row>, DbError>>()?;
+                compacted.insert_rows(&rows)?;
+            }
+            compacted.flush()?;
+            Ok(compacted.num_rows())
+        };
+        copy().inspect_err(|_| {
+            let _ = fs::remove_file(path);
+        })
+    }
+
+    /// Creates a table on top of the given storage, holding the rows recorded in its header
+    pub fn with_storage(storage: Box<dyn Storage>) -> Self {
+        let num_rows = storage.header().num_rows as usize;
+        let encoding = storage.header().encoding;
+
+        let mut table = Table {
+            storage,
+            num_rows,
+            max_pages: TABLE_MAX_PAGES,
+            encoding,
+            index: BTreeMap::new(),
+            username_index: None,
+            reserve_zero_id: false,
+            schema: Schema::default(),
+            read_only: false,
+            modified: false,
+        };
+        table.index = (0..num_rows).fold(BTreeMap::new(), |mut index, position| {
+            if let Some(row_ref) = table.select_row_ref(position) {
+                index.entry(row_ref.id()).or_default().push(position);
+            }
+            index
+        });
+        table
+    }
+
+    /// Sets the number of pages the table may grow to, the default is 100
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    /// The number of pages the table may grow to
+    pub fn max_pages(&self) -> usize {
+        self.max_pages
+    }
+
+    /// Rejects inserts of rows with id 0 with `DbError::InvalidId`. Rows already in the table are not checked.
+    pub fn with_reserved_zero_id(mut self) -> Self {
+        self.reserve_zero_id = true;
+        self
+    }
+
+    /// Names the columns as in `schema`
+    pub fn with_schema(mut self, schema: Schema) -> Self {
+        self.schema = schema;
+        self
+    }
+
+    pub fn schema(&self) -> &Schema {
+        &self.schema
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
+    fn check_writable(&self) -> Result<(), DbError> {
+        if self.read_only {
+            return Err(DbError::ReadOnly);
+        }
+        Ok(())
+    }
+
+    fn validate(&self, row: &Row) -> Result<(), DbError> {
+        if self.reserve_zero_id && row.id == 0 {
+            return Err(DbError::InvalidId(row.id));
+        }
+        row.check_fits(self.encoding)
+    }
+
+    pub fn encoding(&self) -> StringEncoding {
+        self.encoding
+    }
+
+    /// Changes how the text fields of rows are stored, rewriting the rows already in the table. Fails
+    /// without changing anything if a string does not fit its slot with the new encoding.
+    pub fn set_encoding(&mut self, encoding: StringEncoding) -> Result<(), DbError> {
+        self.check_writable()?;
+        if encoding == self.encoding {
+            return Ok(());
+        }
+        let rows = (0..self.num_rows)
+            .filter_map(|position| self.select_row(position).transpose())
+            .collect::<Result<Vec<Row>, DbError>>()?;
+        rows.iter().try_for_each(|row| row.check_fits(encoding))?;
+
+        self.encoding = encoding;
+        self.modified = true;
+        for (position, row) in rows.iter().enumerate() {
+            self.write_row(position, row)?;
+        }
+        // NUL-terminated strings end at an embedded NUL byte
+        if self.username_index.is_some() {
+            self.username_index = Some(self.build_username_index()?);
+        }
+        Ok(())
+    }
+
+    /// Writes the table to its file. Does nothing for in-memory and read-only tables. Dropping a table
+    /// flushes it as well, but ignores errors.
+    pub fn flush(&mut self) -> Result<(), DbError> {
+        if self.read_only {
+            return Ok(());
+        }
+        trace_span!("flush", num_pages = self.num_pages());
+        self.storage.flush(self.header())?;
+        self.modified = false;
+        Ok(())
+    }
+
+    fn header(&self) -> Header {
+        Header { encoding: self.encoding, page_size: self.storage.page_size() as u32, ..Header::new(self.num_rows) }
+    }
+
+    /// Removes all rows from the table, emptying the backing file of file-backed tables
+    pub fn truncate(&mut self) -> Result<(), DbError> {
+        self.check_writable()?;
+        self.storage.truncate()?;
+        self.modified = true;
+        self.num_rows = 0;
+        self.index.clear();
+        if let Some(username_index) = &mut self.username_index {
+            username_index.clear();
+        }
+        Ok(())
+    }
+
+    /// Path of the backing file, `None` for in-memory tables
+    pub fn path(&self) -> Option<&Path> {
+        self.storage.path()
+    }
+
+    /// Bytes of an incomplete last page dropped when a truncated file was opened, see `Config::recover`
+    pub fn dropped_bytes(&self) -> usize {
+        self.storage.dropped_bytes()
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.storage.num_pages()
+    }
+
+    pub fn num_rows(&self) -> usize {
+        self.num_rows
+    }
+
+    /// Number of rows that can still be inserted before `max_pages` is reached and inserts fail with
+    /// `CapacityExceeded`. A limit too large to count up to saturates at `usize::MAX`.
+    pub fn capacity_remaining(&self) -> usize {
+        self.max_pages.saturating_mul(self.rows_per_page()).saturating_sub(self.num_rows)
+    }
+
+    /// Number of bytes taken up by the serialized rows
+    pub fn bytes_used(&self) -> usize {
+        self.num_rows * ROW_SIZE
+    }
+
+    /// How the bytes of the file are used. In-memory tables report the size of their `snapshot`.
+    ///
+    /// Deletes move the last row into the freed slot and drop emptied pages, so the dead bytes stay below
+    /// a page plus the unusable end of every page.
+    pub fn disk_usage(&self) -> DiskUsage {
+        let live_bytes = self.bytes_used();
+        DiskUsage {
+            total_bytes: HEADER_SIZE + self.num_pages() * self.page_size(),
+            live_bytes,
+            dead_bytes: self.num_pages() * self.storage.page_data_size() - live_bytes,
+        }
+    }
+
+    /// Number of bytes of a page, including its checksum
+    pub fn page_size(&self) -> usize {
+        self.storage.page_size()
+    }
+
+    /// Number of rows a page holds
+    pub fn rows_per_page(&self) -> usize {
+        self.storage.page_data_size() / ROW_SIZE
+    }
+
+    /// Number of bytes occupied by rows in the page holding the last row. Computed from the number of rows
+    /// alone, so pages past the rows, e.g. left behind by a crash during a flush, do not matter.
+    pub fn last_page_fill(&self) -> usize {
+        let rows_per_page = self.rows_per_page();
+        let rows_in_last_page = match self.num_rows % rows_per_page {
+            0 if self.num_rows > 0 => rows_per_page,
+            rows => rows,
+        };
+        rows_in_last_page * ROW_SIZE
+    }
+
+    /// Number of bytes in the last page that are not occupied by a row
+    pub fn last_page_free_bytes(&self) -> usize {
+        match self.num_pages() {
+            0 => 0,
+            _ => self.storage.page_data_size() - self.last_page_fill(),
+        }
+    }
+
+    /// Where `insert_row` will store the next row
+    pub fn next_placement(&self) -> Placement {
+        let (page_num, byte_offset) = self.row_position(self.num_rows);
+        Placement { page_num, byte_offset, new_page: page_num >= self.num_pages() }
+    }
+
+    /// Appends the row to the table and returns the position it was stored at.
+    ///
+    /// All checks happen before the table is modified, so a failed insert leaves it unchanged.
+    pub fn insert_row(&mut self, row: &Row) -> Result<usize, DbError> {
+        trace_span!("insert_row", id = row.id);
+        self.check_writable()?;
+        let position = self.num_rows;
+        let (page_num, _) = self.row_position(position);
+
+        if page_num >= self.max_pages {
+            return Err(DbError::CapacityExceeded);
+        }
+        let num_rows = position.checked_add(1).ok_or(DbError::CapacityExceeded)?;
+        self.validate(row)?;
+
+        let encoding = self.encoding;
+        row.serialize_into_with(self.row_slice_mut(position)?, encoding)?;
+        self.num_rows = num_rows;
+        self.index.entry(row.id).or_default().push(position);
+        self.index_username(position);
+
+        Ok(position)
+    }
+
+    /// Replaces the first row with the id of `row`, or appends `row` if there is none
+    pub fn upsert_row(&mut self, row: &Row) -> Result<Upsert, DbError> {
+        self.check_writable()?;
+        self.validate(row)?;
+        match self.index.get(&row.id).and_then(|positions| positions.first()) {
+            Some(&position) => {
+                self.unindex_username(position);
+                self.write_row(position, row)?;
+                self.index_username(position);
+                Ok(Upsert::Replaced(position))
+            }
+            None => Ok(Upsert::Inserted(self.insert_row(row)?)),
+        }
+    }
+
+    /// Overwrites the row at an existing position, the id of the row must not change
+    fn write_row(&mut self, position: usize, row: &Row) -> Result<(), DbError> {
+        let encoding = self.encoding;
+        row.serialize_into_with(self.row_slice_mut(position)?, encoding)
+    }
+
+    /// The bytes of the row at the given position, `None` if its page does not exist. Fails with
+    /// `OutOfBounds` instead of panicking if the page is too short to hold the row.
+    fn row_slice(&self, position: usize) -> Result<Option<&[u8]>, DbError> {
+        let (page_num, byte_offset_in_page) = self.row_position(position);
+        match self.storage.page(page_num) {
+            Some(page) => page.get(byte_offset_in_page..byte_offset_in_page + ROW_SIZE)
+                .map(Some)
+                .ok_or(DbError::OutOfBounds { page: page_num, offset: byte_offset_in_page }),
+            None => Ok(None),
+        }
+    }
+
+    /// Like `row_slice`, allocating the page if it is the next one
+    fn row_slice_mut(&mut self, position: usize) -> Result<&mut [u8], DbError> {
+        self.modified = true;
+        let (page_num, byte_offset_in_page) = self.row_position(position);
+        let out_of_bounds = DbError::OutOfBounds { page: page_num, offset: byte_offset_in_page };
+        match self.storage.page_mut(page_num) {
+            Some(page) => page.get_mut(byte_offset_in_page..byte_offset_in_page + ROW_SIZE).ok_or(out_of_bounds),
+            None => Err(out_of_bounds),
+        }
+    }
+
+    /// Returns the serialized bytes of the row at the given position as they are stored in its page
+    pub fn row_bytes(&self, position: usize) -> Option<&[u8]> {
+        if position >= self.num_rows {
+            return None;
+        }
+        self.row_slice(position).ok().flatten()
+    }
+
+    /// Returns a view of the row at the given position that borrows the page it is stored in
+    pub fn select_row_ref(&self, position: usize) -> Option<RowRef<'_>> {
+        if position >= self.num_rows {
+            return None;
+        }
+        RowRef::with_encoding(self.row_slice(position).ok()??, self.encoding).ok()
+    }
+
+    /// Appends the rows page by page and returns how many were inserted.
+    ///
+    /// Stops with `CapacityExceeded` at the first row that no longer fits, the rows before it stay inserted.
+    /// An invalid row fails the whole batch before anything is inserted.
+    pub fn insert_rows(&mut self, rows: &[Row]) -> Result<usize, DbError> {
+        self.check_writable()?;
+        rows.iter().try_for_each(|row| self.validate(row))?;
+        let mut inserted = 0;
+        while inserted < rows.len() {
+            let (page_num, byte_offset_in_page) = self.row_position(self.num_rows);
+            if page_num >= self.max_pages {
+                return Err(DbError::CapacityExceeded);
+            }
+
+            let rows_in_page = cmp::min(self.rows_per_page() - byte_offset_in_page / ROW_SIZE, rows.len() - inserted);
+            let num_rows = self.num_rows.checked_add(rows_in_page).ok_or(DbError::CapacityExceeded)?;
+            self.modified = true;
+            let page = self.storage.page_mut(page_num).ok_or(DbError::OutOfBounds { page: page_num, offset: byte_offset_in_page })?;
+            for (i, row) in rows[inserted..inserted + rows_in_page].iter().enumerate() {
+                let start = byte_offset_in_page + i * ROW_SIZE;
+                let slot = page.get_mut(start..start + ROW_SIZE).ok_or(DbError::OutOfBounds { page: page_num, offset: start })?;
+                row.serialize_into_with(slot, self.encoding)?;
+            }
+
+            // the rows are indexed once the whole page is written, a failed write leaves no entries behind
+            let first = self.num_rows;
+            self.num_rows = num_rows;
+            for (position, row) in (first..num_rows).zip(&rows[inserted..inserted + rows_in_page]) {
+                self.index.entry(row.id).or_default().push(position);
+                self.index_username(position);
+            }
+            inserted += rows_in_page;
+        }
+
+        Ok(inserted)
+    }
+
+    /// Appends the rows that are not already in the table and returns how many were inserted. Repeated
+    /// rows in `rows` are inserted once.
+    ///
+    /// An invalid row fails the whole batch before anything is inserted.
+    pub fn insert_deduped(&mut self, rows: &[Row]) -> Result<usize, DbError> {
+        self.check_writable()?;
+        rows.iter().try_for_each(|row| self.validate(row))?;
+        let mut seen = HashSet::new();
+        let mut inserted = 0;
+        for row in rows {
+            if !seen.insert(row) || (self.contains_id(row.id) && self.select_all_by_id(row.id)?.contains(row)) {
+                continue;
+            }
+            self.insert_row(row)?;
+            inserted += 1;
+        }
+
+        Ok(inserted)
+    }
+
+    /// Appends the rows of `other` in storage order, keeping ids unique: a row whose id is in this table or
+    /// in an earlier row of `other` is handled as `on_duplicate` says.
+    pub fn merge(&mut self, other: Table, on_duplicate: OnDuplicate) -> Result<MergeSummary, DbError> {
+        self.check_writable()?;
+        let rows = other.select_range(0, other.num_rows())?;
+        if on_duplicate == OnDuplicate::Error {
+            let mut ids = HashSet::new();
+            if let Some(row) = rows.iter().find(|row| self.contains_id(row.id) || !ids.insert(row.id)) {
+                return Err(DbError::DuplicateId(row.id));
+            }
+        }
+
+        let mut summary = MergeSummary { inserted: 0, skipped: 0 };
+        for row in rows {
+            if self.contains_id(row.id) {
+                summary.skipped += 1;
+                continue;
+            }
+            self.insert_row(&row)?;
+            summary.inserted += 1;
+        }
+        Ok(summary)
+    }
+
+    /// Removes the row at the given position by moving the last row into its place, so the position of
+    /// the last row changes. Pages no longer holding any row are dropped.
+    pub fn delete_row(&mut self, position: usize) -> Result<(), DbError> {
+        self.check_writable()?;
+        let last = match self.num_rows.checked_sub(1) {
+            Some(last) if position <= last => last,
+            _ => return Err(DbError::InvalidRow(format!("No row at position {}", position))),
+        };
+        let deleted_id = self.select_row_ref(position).map(|row_ref| row_ref.id());
+        let last_id = self.select_row_ref(last).map(|row_ref| row_ref.id());
+        self.unindex_username(position);
+        if position != last {
+            self.unindex_username(last);
+        }
+
+        if position != last {
+            let mut last_bytes = [0; ROW_SIZE];
+            if let Some(bytes) = self.row_slice(last)? {
+                last_bytes.copy_from_slice(bytes);
+            }
+            self.row_slice_mut(position)?.copy_from_slice(&last_bytes);
+        }
+        self.row_slice_mut(last)?.fill(0);
+        self.num_rows = last;
+        self.storage.truncate_pages(self.num_rows.div_ceil(self.rows_per_page()));
+
+        if let Some(id) = deleted_id {
+            self.remove_from_index(id, position);
+        }
+        if let (Some(id), true) = (last_id, position != last) {
+            self.remove_from_index(id, last);
+            let positions = self.index.entry(id).or_default();
+            let at = positions.partition_point(|&p| p < position);
+            positions.insert(at, position);
+            self.index_username(position);
+        }
+        Ok(())
+    }
+
+    /// Deletes every row matching the predicate and returns how many were deleted
+    pub fn delete_where(&mut self, predicate: impl Fn(&Row) -> bool) -> Result<usize, DbError> {
+        self.check_writable()?;
+        let mut matching = Vec::new();
+        for position in 0..self.num_rows {
+            if let Some(row) = self.select_row(position)? {
+                if predicate(&row) {
+                    matching.push(position);
+                }
+            }
+        }
+        // from the back, so the rows moved into deleted slots have already been checked
+        for &position in matching.iter().rev() {
+            self.delete_row(position)?;
+        }
+        Ok(matching.len())
+    }
+
+    /// Indexes the rows by username, which speeds up `positions_of_username`. The index is kept up to date
+    /// by every change but lives in memory only, it has to be created again after opening the file.
+    /// Returns false if it exists already.
+    pub fn create_username_index(&mut self) -> Result<bool, DbError> {
+        if self.username_index.is_some() {
+            return Ok(false);
+        }
+        self.username_index = Some(self.build_username_index()?);
+        Ok(true)
+    }
+
+    /// Removes the index created by `create_username_index`, returns false if there is none
+    pub fn drop_username_index(&mut self) -> bool {
+        self.username_index.take().is_some()
+    }
+
+    /// The columns the table is indexed by, the id always comes first
+    pub fn indexed_columns(&self) -> Vec<ColumnName> {
+        let mut columns = vec![ColumnName::Id];
+        if self.username_index.is_some() {
+            columns.push(ColumnName::Username);
+        }
+        columns
+    }
+
+    /// Returns the positions of the rows with the given username in storage order, `None` if there is no
+    /// username index
+    pub fn positions_of_username(&self, username: &str) -> Option<Vec<usize>> {
+        let username_index = self.username_index.as_ref()?;
+        Some(username_index.get(username).cloned().unwrap_or_default())
+    }
+
+    fn build_username_index(&self) -> Result<BTreeMap<String, Vec<usize>>, DbError> {
+        let mut username_index: BTreeMap<String, Vec<usize>> = BTreeMap::new();
+        for position in 0..self.num_rows {
+            if let Some(row) = self.select_row(position)? {
+                username_index.entry(row.username).or_default().push(position);
+            }
+        }
+        Ok(username_index)
+    }
+
+    /// The username stored at the position as it reads back, `None` without a username index
+    fn indexed_username(&self, position: usize) -> Option<String> {
+        self.username_index.as_ref()?;
+        self.select_row_ref(position)?.username().ok().map(String::from)
+    }
+
+    /// Adds the row at the position to the username index, if there is one
+    fn index_username(&mut self, position: usize) {
+        if let (Some(username), Some(username_index)) = (self.indexed_username(position), self.username_index.as_mut()) {
+            let positions = username_index.entry(username).or_default();
+            let at = positions.partition_point(|&p| p < position);
+            positions.insert(at, position);
+        }
+    }
+
+    /// Removes the row at the position from the username index, if there is one
+    fn unindex_username(&mut self, position: usize) {
+        if let (Some(username), Some(username_index)) = (self.indexed_username(position), self.username_index.as_mut()) {
+            if let Some(positions) = username_index.get_mut(&username) {
+                positions.retain(|&p| p != position);
+                if positions.is_empty() {
+                    username_index.remove(&username);
+                }
+            }
+        }
+    }
+
+    fn remove_from_index(&mut self, id: u32, position: usize) {
+        if let Some(positions) = self.index.get_mut(&id) {
+            positions.retain(|&p| p != position);
+            if positions.is_empty() {
+                self.index.remove(&id);
+            }
+        }
+    }
+
+    /// Returns the page and the byte-offset in page for a given row number. Does not overflow for any
+    /// `row_num`, the page number is at most `row_num` and the offset is less than the page size.
+    pub fn row_position(&self, row_num: usize) -> (usize, usize) {
+        let rows_per_page = self.rows_per_page();
+        let page_num = row_num / rows_per_page;
+        let row_in_page = row_num % rows_per_page;
+        let byte_offset_in_page = row_in_page * ROW_SIZE;
+        (page_num, byte_offset_in_page)
+    }
+
+    /// Returns the row at the given position, or an error if its bytes cannot be deserialized
+    pub fn select_row(&self, position: usize) -> Result<Option<Row>, DbError> {
+        let bytes = match self.row_slice(position)? {
+            Some(bytes) => bytes,
+            None => return Ok(None),
+        };
+        let row = Row::deserialize_with(bytes, self.encoding).map_err(DbError::InvalidRow)?;
+
+        Ok(Some(row))
+    }
+
+    /// Returns the first row with the given id
+    pub fn select_by_id(&self, id: u32) -> Result<Option<Row>, DbError> {
+        trace_span!("select_by_id", id = id);
+        match self.index.get(&id).and_then(|positions| positions.first()) {
+            Some(&position) => self.select_row(position),
+            None => Ok(None),
+        }
+    }
+
+    /// Tells whether a row with the given id exists, looking only at the id index
+    pub fn contains_id(&self, id: u32) -> bool {
+        self.index.contains_key(&id)
+    }
+
+    /// Returns the positions of all rows with the given id in storage order. Ids are not unique,
+    /// several rows may share one.
+    pub fn positions_of_id(&self, id: u32) -> Vec<usize> {
+        self.index.get(&id).cloned().unwrap_or_default()
+    }
+
+    /// Returns the row with the lowest id, the first stored one if several share it
+    pub fn first_by_id(&self) -> Result<Option<Row>, DbError> {
+        match self.index.first_key_value().and_then(|(_, positions)| positions.first()) {
+            Some(&position) => self.select_row(position),
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the row with the highest id, the last stored one if several share it
+    pub fn last_by_id(&self) -> Result<Option<Row>, DbError> {
+        match self.index.last_key_value().and_then(|(_, positions)| positions.last()) {
+            Some(&position) => self.select_row(position),
+            None => Ok(None),
+        }
+    }
+
+    /// The highest id in the table, read from the id index without decoding any row
+    pub fn max_id(&self) -> Option<u32> {
+        self.index.last_key_value().map(|(&id, _)| id)
+    }
+
+    /// Positions of the rows whose ids are in `range` ordered by id, rows sharing an id are in storage order
+    pub fn positions_in_id_range(&self, range: impl RangeBounds<u32>) -> impl Iterator<Item = usize> + '_ {
+        self.index.range(range).flat_map(|(_, positions)| positions.iter().copied())
+    }
+
+    /// Positions of all rows ordered by id, rows sharing an id are in storage order
+    pub fn positions_by_id(&self) -> impl Iterator<Item = usize> + '_ {
+        self.index.values().flatten().copied()
+    }
+
+    /// Returns all rows with the given id in storage order
+    pub fn select_all_by_id(&self, id: u32) -> Result<Vec<Row>, DbError> {
+        self.positions_of_id(id)
+            .into_iter()
+            .filter_map(|position| self.select_row(position).transpose())
+            .collect()
+    }
+
+    /// Returns the values of the column without repetitions, ordered by the id of the row each value is
+    /// first seen in
+    pub fn distinct_values(&self, column: ColumnName) -> Result<Vec<String>, DbError> {
+        let mut seen = HashSet::new();
+        let mut values = Vec::new();
+        for position in self.positions_by_id() {
+            if let Some(row) = self.select_row(position)? {
+                let value = column.value(&row);
+                if seen.insert(value.clone()) {
+                    values.push(value);
+                }
+            }
+        }
+        Ok(values)
+    }
+
+    /// Counts the rows holding each value of the column, ordered by value
+    pub fn count_by(&self, column: ColumnName) -> Result<BTreeMap<String, usize>, DbError> {
+        let mut counts = BTreeMap::new();
+        for position in 0..self.num_rows {
+            if let Some(row) = self.select_row(position)? {
+                *counts.entry(column.value(&row)).or_insert(0) += 1;
+            }
+        }
+        Ok(counts)
+    }
+
+    /// Returns the rows at the positions `start..end` in storage order, see `select_positions`
+    pub fn select_range(&self, start: usize, end: usize) -> Result<Vec<Row>, DbError> {
+        self.select_positions(start..end)
+    }
+
+    /// Returns the rows at the positions in `range` in storage order, the end is clamped to the number of
+    /// rows. Every page overlapping the range is looked up once and its rows are read straight from it
+    /// into a vector allocated once for the whole range, e.g. for the visible rows of a scrolling view.
+    pub fn select_positions(&self, range: Range<usize>) -> Result<Vec<Row>, DbError> {
+        let end = cmp::min(range.end, self.num_rows);
+        let mut rows = Vec::with_capacity(end.saturating_sub(range.start));
+        let rows_per_page = self.rows_per_page();
+        let mut position = range.start;
+        while position < end {
+            let (page_num, start_offset) = self.row_position(position);
+            let rows_in_page = cmp::min(rows_per_page - start_offset / ROW_SIZE, end - position);
+            let page = match self.storage.page(page_num) {
+                Some(page) => page,
+                None => break,
+            };
+            for offset in (start_offset..).step_by(ROW_SIZE).take(rows_in_page) {
+                let bytes = page.get(offset..offset + ROW_SIZE).ok_or(DbError::OutOfBounds { page: page_num, offset })?;
+                let row_ref = RowRef::with_encoding(bytes, self.encoding).map_err(DbError::InvalidRow)?;
+                rows.push(row_ref.to_row().map_err(DbError::InvalidRow)?);
+            }
+            position += rows_in_page;
+        }
+        Ok(rows)
+    }
+}
+
+/// Iterates over the rows of a table in storage order.
+///
+/// Panics if a row cannot be deserialized, use `Table::select_row` to handle corrupt data.
+pub struct TableIterator<'a> {
+    table: &'a Table,
+    position: usize,
+    // one past the last position still to be yielded from the back
+    end: usize,
+}
+
+impl TableIterator<'_> {
+    /// Returns the row `next` would return without advancing the iterator
+    pub fn peek(&self) -> Option<Row> {
+        if self.position >= self.end {
+            return None;
+        }
+        self.table.select_row(self.position).expect("corrupt row")
+    }
+
+    /// Moves the iterator to the given position and returns it. Positions past the end are clamped to
+    /// the end, which is `num_rows()` unless rows were taken from the back.
+    pub fn seek(&mut self, position: usize) -> usize {
+        self.position = cmp::min(position, self.end);
+        self.position
+    }
+}
+
+impl<'a> Iterator for TableIterator<'a> {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position >= self.end {
+            return None;
+        }
+        let row = self.table.select_row(self.position).expect("corrupt row");
+        self.position += 1;
+        row
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = self.end - self.position;
+        (remaining, Some(remaining))
+    }
+}
+
+impl<'a> ExactSizeIterator for TableIterator<'a> {}
+
+impl<'a> DoubleEndedIterator for TableIterator<'a> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.end <= self.position {
+            return None;
+        }
+        self.end -= 1;
+        self.table.select_row(self.end).expect("corrupt row")
+    }
+}
+
+impl Table {
+    pub fn iter(&self) -> TableIterator<'_> {
+        TableIterator {
+            table: self,
+            position: 0,
+            end: self.num_rows(),
+        }
+    }
+
+    /// A cursor over the rows the table holds now, see `Cursor`
+    pub fn cursor(&self) -> Cursor {
+        Cursor { position: 0, end: self.num_rows }
+    }
+}
+
+/// Steps through the rows that existed when the cursor was created, in storage order.
+///
+/// Unlike `TableIterator` a cursor does not borrow the table, so rows may be inserted between steps.
+/// Rows appended after the cursor was created are never returned. Deleting rows moves the last row into
+/// the freed position, so a scan interleaved with deletes may skip or repeat rows.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cursor {
+    position: usize,
+    // number of rows when the cursor was created
+    end: usize,
+}
+
+impl Cursor {
+    /// Returns the row at the cursor and advances it, `None` once all rows have been returned
+    pub fn next(&mut self, table: &Table) -> Result<Option<Row>, DbError> {
+        if self.position >= cmp::min(self.end, table.num_rows()) {
+            return Ok(None);
+        }
+        let row = table.select_row(self.position)?;
+        self.position += 1;
+        Ok(row)
+    }
+
+    pub fn position(&self) -> usize {
+        self.position
+    }
+}
+
+impl<'a> IntoIterator for &'a Table {
+    type Item = Row;
+    type IntoIter = TableIterator<'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        TableIterator {
+            table: self,
+            position: 0,
+            end: self.num_rows(),
+        }
+    }
+}
+
+impl Table {
+    /// Creates an in-memory table holding the rows as if they were inserted one by one with `insert_row`,
+    /// stops at the first row it rejects
+    pub fn from_rows<I: IntoIterator<Item = Row>>(rows: I) -> Result<Table, DbError> {
+        let mut table = Table::new();
+        for row in rows {
+            table.insert_row(&row)?;
+        }
+        Ok(table)
+    }
+}
+
+/// Panics if a row is rejected, e.g. once the table is full. Use `Table::from_rows` to handle the error.
+impl FromIterator<Row> for Table {
+    fn from_iter<I: IntoIterator<Item = Row>>(rows: I) -> Self {
+        Table::from_rows(rows).expect("row rejected")
+    }
+}
+
+/// Appends the rows with `insert_row`. Panics if a row is rejected, the rows before it stay inserted.
+impl Extend<Row> for Table {
+    fn extend<I: IntoIterator<Item = Row>>(&mut self, rows: I) {
+        for row in rows {
+            self.insert_row(&row).expect("row rejected");
+        }
+    }
+}
+
+/// Writes the changes since the last flush to the file of a file-backed table, see `Table::flush`. A
+/// failed write is reported as a `flush_failed` event of the `trace` module and otherwise ignored, since
+/// panicking in `drop` would abort a thread that is already unwinding. Call `flush` to handle the error.
+impl Drop for Table {
+    fn drop(&mut self) {
+        if self.modified && self.path().is_some() {
+            if let Err(_error) = self.flush() {
+                trace_event!("flush_failed", error = _error);
+            }
+        }
+    }
+}
+
+/// Collects the settings of a table, which `build` checks and opens it with. Settings left out keep
+/// the defaults of `Table::new`.
+#[derive(Debug, Default)]
+pub struct TableBuilder {
+    path: Option<PathBuf>,
+    page_size: Option<usize>,
+    cache_size: Option<usize>,
+    max_pages: Option<usize>,
+    schema: Option<Schema>,
+    on_truncated: OnTruncated,
+    read_only: bool,
+}
+
+impl TableBuilder {
+    /// Stores the table in the file at `path`, see `FileStorage::open`. Without one the table is kept in
+    /// memory.
+    pub fn path(mut self, path: impl AsRef<Path>) -> Self {
+        self.path = Some(path.as_ref().to_path_buf());
+        self
+    }
+
+    /// Page size in bytes of a new table, an existing file must use the same size
+    pub fn page_size(mut self, page_size: usize) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    /// Number of pages kept in memory. The storage keeps every page in memory, so this also limits the
+    /// number of pages unless `max_pages` is given.
+    pub fn cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    /// Number of pages the table may grow to, see `Table::with_max_pages`
+    pub fn max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = Some(max_pages);
+        self
+    }
+
+    /// Names the columns as in `schema`
+    pub fn schema(mut self, schema: Schema) -> Self {
+        self.schema = Some(schema);
+        self
+    }
+
+    /// Opens the file for reading only, see `Table::open_readonly`. Requires a path.
+    pub fn read_only(mut self, read_only: bool) -> Self {
+        self.read_only = read_only;
+        self
+    }
+
+    /// How a truncated file is opened, see `FileStorage::open_with`
+    pub fn on_truncated(mut self, on_truncated: OnTruncated) -> Self {
+        self.on_truncated = on_truncated;
+        self
+    }
+
+    /// Checks the settings and opens the table. Sizes must be positive, the table cannot grow to more
+    /// pages than the cache holds, and only a file can be opened read-only.
+    pub fn build(self) -> Result<Table, DbError> {
+        if let Some(page_size) = self.page_size {
+            check_page_size(page_size)?;
+        }
+        if self.cache_size == Some(0) || self.max_pages == Some(0) {
+            return Err(DbError::InvalidConfig(String::from("cache size and max pages must be positive")));
+        }
+        if let (Some(cache_size), Some(max_pages)) = (self.cache_size, self.max_pages) {
+            if max_pages > cache_size {
+                return Err(DbError::InvalidConfig(format!(
+                    "max pages {} exceed the cache size of {} pages, every page is kept in memory",
+                    max_pages, cache_size
+                )));
+            }
+        }
+
+        let storage: Box<dyn Storage> = match &self.path {
+            Some(path) if self.read_only => Box::new(FileStorage::open_read_only(path, self.page_size, self.on_truncated)?),
+            Some(path) => Box::new(FileStorage::open_with(path, self.page_size, self.on_truncated)?),
+            None if self.read_only => {
+                return Err(DbError::InvalidConfig(String::from("only a database file can be opened read-only")));
+            }
+            None => Box::new(MemoryStorage::new(self.page_size.unwrap_or(PAGE_SIZE))),
+        };
+        let mut table = Table::with_storage(storage)
+            .with_max_pages(self.max_pages.or(self.cache_size).unwrap_or(TABLE_MAX_PAGES));
+        table.read_only = self.read_only;
+        Ok(match self.schema {
+            Some(schema) => table.with_schema(schema),
+            None => table,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::seq::SliceRandom;
+    use rand::Rng;
+    use crate::config::Config;
+    use crate::error::DbError;
+    use crate::header::{Header, HEADER_SIZE};
+    use crate::pager::{FileStorage, MemoryStorage, Storage, PAGE_DATA_SIZE, PAGE_SIZE};
+    use crate::row::{ColumnName, Row, StringEncoding, ROW_SIZE, USERNAME_OFFSET, USERNAME_SIZE};
+    use crate::schema::Schema;
+    use crate::table::{DiskUsage, MergeSummary, OnDuplicate, Placement, ROWS_PER_PAGE, Table, TABLE_MAX_PAGES, Upsert};
+    use crate::util::{gen_random_row, user_row};
+
+    #[test]
+    fn row_position() -> Result<(), String> {
+        let table = Table::new();
+        assert_eq!(table.row_position(0), (0, 0));
+        assert_eq!(table.row_position(ROWS_PER_PAGE), (1, 0));
+        assert_eq!(table.row_position(ROWS_PER_PAGE + 10), (1, 10 * ROW_SIZE));
+        assert_eq!(table.row_position((TABLE_MAX_PAGES + 1) * ROWS_PER_PAGE), (TABLE_MAX_PAGES + 1, 0));
+        assert_eq!(table.row_position(usize::MAX), (usize::MAX / ROWS_PER_PAGE, usize::MAX % ROWS_PER_PAGE * ROW_SIZE));
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_row_and_select() -> Result<(), String> {
+        let mut table = Table::new();
+        let row = Row::new(100, "Yz85rmUs0CzYJBDDA6hY38I07uOq6u2R", "qopa@apoq.com")?;
+
+        assert_eq!(table.insert_row(&row)?, 0);
+
+        let page = table.storage.page(0);
+        assert!(page.is_some());
+        assert_eq!(table.num_rows(), 1);
+
+        let row_from_table = table.select_row(0)?.unwrap();
+        assert_eq!(row, row_from_table);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_and_select_multiple_rows() -> Result<(), String> {
+        let rows = [
+            Row::from_string("10 Andrew andre.jung@gmail.com")?,
+            Row::from_string("30 Birte birte.hochlander@web.de")?,
+            Row::from_string("20 Yanik yk@nomail.com")?,
+        ];
+
+        let mut table = Table::new();
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(table.insert_row(row)?, i);
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            let table_row = table.select_row(i)?.unwrap();
+            assert_eq!(*row, table_row);
+        }
+
+        Ok(())
+    }
+
+
+
+    #[test]
+    fn insert_and_select_lots_of_rows() -> Result<(), String> {
+        let mut rng = rand::thread_rng();
+        let num_rows = 1000;
+        let mut rows: Vec<Row> = Vec::new();
+        let mut table = Table::new();
+
+        for _i in 0..num_rows {
+            let random_id = rng.gen();
+            let row = gen_random_row(&mut rng, random_id);
+
+            table.insert_row(&row)?;
+            rows.push(row);
+        }
+
+        for (i, row) in rows.iter().enumerate() {
+            let table_row = table.select_row(i)?.unwrap();
+            assert_eq!(*row, table_row);
+        }
+
+        Ok(())
+    }
+
+
+    #[test]
+    fn test_iterator() -> Result<(), String> {
+        let mut table = Table::new();
+        let row1 = Row::new(100, "foo", "bar@baz.com")?;
+        let row2 = Row::new(200, "baz", "bam@baz.com")?;
+
+        table.insert_row(&row1)?;
+        table.insert_row(&row2)?;
+
+        let mut iter = table.iter();
+        assert_eq!(iter.next().unwrap(), row1);
+        assert_eq!(iter.next().unwrap(), row2);
+        assert!(iter.next().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reverse_iterator() -> Result<(), String> {
+        let mut table = Table::new();
+        let rows: Vec<Row> = (0..5)
+            .map(user_row)
+            .collect();
+        for row in rows.iter() {
+            table.insert_row(row)?;
+        }
+
+        let reversed: Vec<Row> = table.iter().rev().collect();
+        assert!(reversed.iter().eq(rows.iter().rev()));
+
+        // taking from both ends yields every row exactly once
+        let mut iter = table.iter();
+        assert_eq!(iter.next().unwrap(), rows[0]);
+        assert_eq!(iter.next_back().unwrap(), rows[4]);
+        assert_eq!(iter.next_back().unwrap(), rows[3]);
+        assert_eq!(iter.next().unwrap(), rows[1]);
+        assert_eq!(iter.next().unwrap(), rows[2]);
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+
+        Ok(())
+    }
+
+    #[test]
+    fn peek() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::from_string("1 a a@x.io")?)?;
+        table.insert_row(&Row::from_string("2 b b@x.io")?)?;
+
+        let mut iter = table.iter();
+        assert_eq!(iter.peek(), Some(Row::from_string("1 a a@x.io")?));
+        assert_eq!(iter.peek(), Some(Row::from_string("1 a a@x.io")?));
+        assert_eq!(iter.next(), Some(Row::from_string("1 a a@x.io")?));
+        assert_eq!(iter.peek(), Some(Row::from_string("2 b b@x.io")?));
+        assert_eq!(iter.len(), 1);
+        iter.next();
+        assert_eq!(iter.peek(), None);
+
+        // the standard adapter works as well
+        let mut peekable = table.iter().peekable();
+        assert_eq!(peekable.peek().map(|row| row.id), Some(1));
+        assert_eq!(peekable.next().map(|row| row.id), Some(1));
+
+        Ok(())
+    }
+
+    #[test]
+    fn cursor_ignores_later_inserts() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_rows(&[user_row(1), user_row(2), user_row(3)])?;
+
+        let mut cursor = table.cursor();
+        let mut ids = Vec::new();
+        while let Some(read) = cursor.next(&table)? {
+            ids.push(read.id);
+            table.insert_row(&user_row(read.id + 10))?;
+        }
+        assert_eq!(ids, [1, 2, 3]);
+        assert_eq!(cursor.position(), 3);
+        assert_eq!(table.num_rows(), 6);
+
+        Ok(())
+    }
+
+    #[test]
+    fn seek() -> Result<(), String> {
+        let rows: Vec<Row> = (1..=5).map(user_row).collect();
+        let mut table = Table::new();
+        table.insert_rows(&rows)?;
+
+        let mut iter = table.iter();
+        assert_eq!(iter.seek(2), 2);
+        assert_eq!(iter.collect::<Vec<Row>>(), rows[2..]);
+
+        let mut iter = table.iter();
+        iter.next_back();
+        assert_eq!(iter.seek(10), 4);
+        assert_eq!(iter.len(), 0);
+        assert_eq!(iter.seek(0), 0);
+        assert_eq!(iter.len(), 4);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_iterator_len() -> Result<(), String> {
+        let mut table = Table::new();
+        for id in 0..3 {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
+        }
+
+        let mut iter = table.iter();
+        assert_eq!(iter.len(), 3);
+        assert_eq!(iter.size_hint(), (3, Some(3)));
+        iter.next();
+        assert_eq!(iter.len(), 2);
+        iter.next_back();
+        assert_eq!(iter.size_hint(), (1, Some(1)));
+        iter.next();
+        assert_eq!(iter.len(), 0);
+        assert!(iter.next().is_none());
+        assert_eq!(iter.size_hint(), (0, Some(0)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_into_iterator() -> Result<(), String> {
+        let mut table = Table::new();
+
+        // empty table
+        assert!((&table).into_iter().next().is_none());
+
+        // table with two items
+        let rows = [Row::new(100, "foo", "bar@baz.com")?,
+            Row::new(200, "baz", "bam@baz.com")?];
+
+        table.insert_row(&rows[0])?;
+        table.insert_row(&rows[1])?;
+
+        for (i, r) in (&table).into_iter().enumerate() {
+            assert_eq!(r, rows[i]);
+        }
+
+        Ok(())
+    }
+
+    /// A storage whose last page is cut short after `len` bytes, like a truncated page read from a file
+    #[derive(Debug)]
+    struct ShortLastPage {
+        inner: MemoryStorage,
+        len: usize,
+    }
+
+    impl Storage for ShortLastPage {
+        fn path(&self) -> Option<&std::path::Path> { None }
+        fn header(&self) -> &Header { self.inner.header() }
+        fn page_size(&self) -> usize { self.inner.page_size() }
+        fn num_pages(&self) -> usize { self.inner.num_pages() }
+        fn page(&self, page_num: usize) -> Option<&[u8]> {
+            let page = self.inner.page(page_num)?;
+            Some(if page_num + 1 == self.num_pages() { &page[..self.len] } else { page })
+        }
+        fn page_mut(&mut self, page_num: usize) -> Option<&mut [u8]> {
+            let last = page_num + 1 == self.num_pages();
+            let page = self.inner.page_mut(page_num)?;
+            Some(if last { &mut page[..self.len] } else { page })
+        }
+        fn truncate_pages(&mut self, num_pages: usize) { self.inner.truncate_pages(num_pages) }
+        fn truncate(&mut self) -> std::io::Result<()> { self.inner.truncate() }
+        fn image(&self, header: Header) -> Vec<u8> { self.inner.image(header) }
+        fn flush(&mut self, header: Header) -> std::io::Result<()> { self.inner.flush(header) }
+    }
+
+    #[test]
+    fn short_last_page() -> Result<(), String> {
+        let mut full = Table::new();
+        for id in 0..(ROWS_PER_PAGE as u32 + 3) {
+            full.insert_row(&Row::new(id, format!("user{}", id), format!("user{}@example.com", id))?)?;
+        }
+        let inner = MemoryStorage::from_image(&full.snapshot())?;
+        // room for the first row of the last page and half of the second one
+        let table = Table::with_storage(Box::new(ShortLastPage { inner, len: ROW_SIZE + ROW_SIZE / 2 }));
+
+        assert_eq!(table.select_row(ROWS_PER_PAGE)?, full.select_row(ROWS_PER_PAGE)?);
+        let offset = ROW_SIZE;
+        assert!(matches!(table.select_row(ROWS_PER_PAGE + 1), Err(DbError::OutOfBounds { page: 1, offset: o }) if o == offset));
+        assert!(matches!(table.select_row(ROWS_PER_PAGE + 2), Err(DbError::OutOfBounds { .. })));
+        assert!(table.select_row_ref(ROWS_PER_PAGE + 1).is_none());
+        assert!(table.row_bytes(ROWS_PER_PAGE + 1).is_none());
+        assert!(matches!(table.select_range(0, table.num_rows()), Err(DbError::OutOfBounds { .. })));
+
+        // a batch failing in the middle of a page indexes none of its rows
+        let inner = MemoryStorage::from_image(&Table::from_rows([user_row(1)])?.snapshot())?;
+        let mut table = Table::with_storage(Box::new(ShortLastPage { inner, len: 2 * ROW_SIZE + ROW_SIZE / 2 }));
+        assert!(matches!(table.insert_rows(&[user_row(2), user_row(3)]), Err(DbError::OutOfBounds { .. })));
+        assert_eq!(table.num_rows(), 1);
+        assert!(table.positions_of_id(2).is_empty());
+        assert_eq!(table.positions_by_id().collect::<Vec<usize>>(), [0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn storage_backends_agree() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-table-backends-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut memory = Table::with_storage(Box::new(MemoryStorage::new(1024)));
+        let mut file = Table::with_storage(Box::new(FileStorage::open(&path, Some(1024))?));
+        for table in [&mut memory, &mut file] {
+            for id in [5, 3, 9, 3, 1, 7, 2] {
+                table.insert_row(&Row::new(id, format!("user{}", id), format!("user{}@example.com", id))?)?;
+            }
+            table.delete_row(1)?;
+            table.upsert_row(&Row::from_string("7 seven seven@example.com")?)?;
+            table.flush()?;
+        }
+
+        assert_eq!(memory.num_pages(), 2);
+        assert_eq!(memory.iter().collect::<Vec<Row>>(), file.iter().collect::<Vec<Row>>());
+        assert_eq!(memory.positions_by_id().collect::<Vec<_>>(), file.positions_by_id().collect::<Vec<_>>());
+        assert_eq!(memory.snapshot(), file.snapshot());
+        assert_eq!(std::fs::read(&path).map_err(|e| e.to_string())?, memory.snapshot());
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn select_range() -> Result<(), String> {
+        let table: Table = (0..(ROWS_PER_PAGE as u32 * 2))
+            .map(user_row)
+            .collect();
+
+        let start = ROWS_PER_PAGE - 2;
+        let end = ROWS_PER_PAGE + 3;
+        let expected = (start..end).map(|position| table.select_row(position)).collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(table.select_range(start, end)?.into_iter().map(Some).collect::<Vec<_>>(), expected);
+
+        assert_eq!(table.select_range(0, usize::MAX)?, table.iter().collect::<Vec<Row>>());
+        assert!(table.select_range(5, 5)?.is_empty());
+        assert!(table.select_range(10, 3)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn select_positions() -> Result<(), String> {
+        // three rows per page
+        let mut table = Table::builder().page_size(1024).build()?;
+        let rows: Vec<Row> = (0..10).map(|id| Row::new(id, format!("user{}", id), format!("user{}@example.com", id))).collect::<Result<_, _>>()?;
+        table.insert_rows(&rows)?;
+        assert_eq!(table.rows_per_page(), 3);
+
+        assert_eq!(table.select_positions(2..8)?, rows[2..8]);
+        assert_eq!(table.select_positions(3..6)?, rows[3..6]);
+        assert_eq!(table.select_positions(7..100)?, rows[7..]);
+        assert_eq!(table.select_positions(0..10)?, rows);
+        assert!(table.select_positions(10..12)?.is_empty());
+        assert!(table.select_positions(4..4)?.is_empty());
+
+        Ok(())
+    }
+
+    #[test]
+    fn collect_and_extend() -> Result<(), String> {
+        let rows = || (1..=3).map(user_row);
+
+        let mut table: Table = rows().collect();
+        assert_eq!(table.num_rows(), 3);
+        assert_eq!(table.select_by_id(2)?, rows().nth(1));
+
+        table.extend(rows());
+        assert_eq!(table.num_rows(), 6);
+        assert_eq!(table.positions_of_id(1), [0, 3]);
+        assert!(table.iter().eq(rows().chain(rows())));
+
+        let too_many = (0..).map(user_row);
+        let too_many = too_many.take(TABLE_MAX_PAGES * ROWS_PER_PAGE + 1);
+        assert!(matches!(Table::from_rows(too_many), Err(DbError::CapacityExceeded)));
+
+        Ok(())
+    }
+
+    #[test]
+    fn from_rows() -> Result<(), String> {
+        let rows = [
+            Row::new(3, "c", "c@x.io")?,
+            Row::new(1, "a", "a@x.io")?,
+            Row::new(2, "b", "b@x.io")?,
+        ];
+        let mut inserted = Table::new();
+        for row in &rows {
+            inserted.insert_row(row)?;
+        }
+
+        let table = Table::from_rows(rows)?;
+        assert_eq!(table.snapshot(), inserted.snapshot());
+        assert_eq!(table.positions_by_id().collect::<Vec<_>>(), [1, 2, 0]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_rows_matches_single_inserts() -> Result<(), String> {
+        let mut rng = rand::thread_rng();
+        let rows: Vec<Row> = (0..1000)
+            .map(|_| {
+                let id = rng.gen();
+                gen_random_row(&mut rng, id)
+            })
+            .collect();
+
+        let mut single = Table::new();
+        for row in rows.iter() {
+            single.insert_row(row)?;
+        }
+
+        // start mid-page so the first batch only partially fills it
+        let mut batched = Table::new();
+        assert_eq!(batched.insert_rows(&rows[..3])?, 3);
+        assert_eq!(batched.insert_rows(&rows[3..])?, rows.len() - 3);
+
+        assert_eq!(batched.num_rows(), single.num_rows());
+        assert_eq!(batched.num_pages(), single.num_pages());
+        assert!(batched.iter().eq(single.iter()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn insert_rows_stops_at_capacity() -> Result<(), String> {
+        let capacity = TABLE_MAX_PAGES * ROWS_PER_PAGE;
+        let rows: Vec<Row> = (0..capacity as u32 + 5)
+            .map(user_row)
+            .collect();
+
+        let mut table = Table::new();
+        assert!(matches!(table.insert_rows(&rows), Err(DbError::CapacityExceeded)));
+        assert_eq!(table.num_rows(), capacity);
+        assert_eq!(table.select_row(capacity - 1)?.unwrap(), rows[capacity - 1]);
+
+        Ok(())
+    }
 
-type Page = [u8; PAGE_SIZE];
+    #[test]
+    fn default() {
+        #[derive(Default)]
+        struct Session {
+            table: Table,
+            config: Config,
+        }
 
-#[derive(Debug)]
-pub struct Table {
-    pages: Vec<Page>,
-    num_rows: usize,
-}
+        let session = Session::default();
+        assert_eq!(session.table.num_rows(), 0);
+        assert_eq!(session.table.schema(), &Schema::default());
+        assert_eq!(session.config, Config::default());
+        assert_eq!(Table::default().num_rows(), 0);
+    }
 
-impl Table {
-    pub fn new() -> Self {
-        Table {
-            pages: Vec::new(),
-            num_rows: 0,
+    #[test]
+    fn first_and_last_by_id() -> Result<(), String> {
+        let mut table = Table::new();
+        assert_eq!(table.first_by_id()?, None);
+        assert_eq!(table.last_by_id()?, None);
+        assert_eq!(table.max_id(), None);
+
+        for line in ["5 e e@x.io", "2 b b@x.io", "9 i i@x.io", "2 bb bb@x.io", "9 ii ii@x.io"] {
+            table.insert_row(&Row::from_string(line)?)?;
         }
+        assert_eq!(table.first_by_id()?, Some(Row::from_string("2 b b@x.io")?));
+        assert_eq!(table.last_by_id()?, Some(Row::from_string("9 ii ii@x.io")?));
+        assert_eq!(table.max_id(), Some(9));
+
+        Ok(())
     }
 
-    pub fn num_pages(&self) -> usize {
-        self.pages.len()
+    #[test]
+    fn merge() -> Result<(), String> {
+        let table = |ids: &[u32]| -> Result<Table, DbError> {
+            Table::from_rows(ids.iter().copied().map(user_row))
+        };
+
+        let mut merged = table(&[1, 2])?;
+        assert_eq!(merged.merge(table(&[3, 4])?, OnDuplicate::Error)?, MergeSummary { inserted: 2, skipped: 0 });
+        assert_eq!(merged.iter().map(|row| row.id).collect::<Vec<u32>>(), [1, 2, 3, 4]);
+
+        // the first of the rows sharing an id wins
+        let summary = merged.merge(table(&[4, 5, 6, 5])?, OnDuplicate::Skip)?;
+        assert_eq!(summary, MergeSummary { inserted: 2, skipped: 2 });
+        assert_eq!(merged.num_rows(), 6);
+
+        assert!(matches!(merged.merge(table(&[7, 2])?, OnDuplicate::Error), Err(DbError::DuplicateId(2))));
+        assert!(matches!(merged.merge(table(&[8, 8])?, OnDuplicate::Error), Err(DbError::DuplicateId(8))));
+        assert_eq!(merged.num_rows(), 6);
+
+        Ok(())
     }
 
-    pub fn num_rows(&self) -> usize {
-        self.num_rows
+    #[test]
+    fn contains_id() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::new(3, "foo", "bar@baz.com")?)?;
+        assert!(table.contains_id(3));
+        assert!(!table.contains_id(4));
+
+        table.delete_row(0)?;
+        assert!(!table.contains_id(3));
+
+        Ok(())
     }
 
-    fn add_page(&mut self) {
-        self.pages.push([0; PAGE_SIZE]);
+    #[test]
+    fn insert_deduped() -> Result<(), String> {
+        let row = |id: u32, username: &str| Row::new(id, username, "bar@baz.com");
+        let mut table = Table::new();
+        table.insert_row(&row(1, "al")?)?;
+
+        let rows = [row(1, "al")?, row(2, "bo")?, row(1, "cy")?, row(2, "bo")?, row(3, "di")?, row(3, "di")?];
+        assert_eq!(table.insert_deduped(&rows)?, 3);
+        assert_eq!(table.num_rows(), 4);
+        assert_eq!(table.insert_deduped(&rows)?, 0);
+
+        Ok(())
     }
 
-    pub fn insert_row(&mut self, row: &Row) -> Result<(), String> {
-        let (page_num, byte_offset_in_page) = Table::row_position(self.num_rows);
+    #[test]
+    fn num_rows_overflow() -> Result<(), String> {
+        let mut table = Table::new().with_max_pages(usize::MAX);
+        table.num_rows = usize::MAX;
+        let row = Row::new(1, "foo", "bar@baz.com")?;
+        assert!(matches!(table.insert_row(&row), Err(DbError::CapacityExceeded)));
+        assert!(matches!(table.insert_rows(&[row]), Err(DbError::CapacityExceeded)));
+        assert_eq!(table.num_rows(), usize::MAX);
 
-        if page_num > TABLE_MAX_PAGES {
-            return Err(String::from("Reached max number of pages"));
-        } else if page_num >= self.pages.len() {
-            self.add_page();
-        }
+        Ok(())
+    }
+
+    #[test]
+    fn builder() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-builder-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
 
-        let page = self.pages.get_mut(page_num).unwrap();
+        let schema = Schema::default().rename(ColumnName::Email, "contact")?;
+        let mut table = Table::builder()
+            .path(&path)
+            .page_size(1024)
+            .cache_size(8)
+            .max_pages(4)
+            .schema(schema.clone())
+            .build()?;
+        assert_eq!(table.path(), Some(path.as_path()));
+        assert_eq!(table.page_size(), 1024);
+        assert_eq!(table.max_pages(), 4);
+        assert_eq!(table.schema(), &schema);
+        table.flush()?;
+        drop(table);
 
-        let row_bytes = row.serialize();
-        for (i, b) in row_bytes.iter().enumerate() {
-            page[byte_offset_in_page + i] = *b;
-        }
-        self.num_rows += 1;
+        // the cache size limits the pages unless max pages are given
+        let table = Table::builder().cache_size(8).build()?;
+        assert_eq!((table.path(), table.page_size(), table.max_pages()), (None, PAGE_SIZE, 8));
+        assert_eq!(Table::builder().build()?.max_pages(), TABLE_MAX_PAGES);
 
+        assert!(matches!(Table::builder().page_size(16).build(), Err(DbError::InvalidPageSize(16))));
+        assert!(matches!(Table::builder().max_pages(0).build(), Err(DbError::InvalidConfig(_))));
+        assert!(matches!(Table::builder().cache_size(2).max_pages(3).build(), Err(DbError::InvalidConfig(_))));
+        // an existing file keeps its page size
+        assert_eq!(Table::builder().path(&path).build()?.page_size(), 1024);
+        assert!(matches!(Table::builder().path(&path).page_size(2048).build(), Err(DbError::PageSizeMismatch { .. })));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
         Ok(())
     }
 
-    /// Returns the page and the byte-offset in page for a given row number
-    fn row_position(row_num: usize) -> (usize, usize) {
-        let page_num = row_num / ROWS_PER_PAGE;
-        let row_in_page = row_num % ROWS_PER_PAGE;
-        let byte_offset_in_page = row_in_page * ROW_SIZE;
-        (page_num, byte_offset_in_page)
+    #[test]
+    fn read_only() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-read-only-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut table = Table::open(&path)?;
+        table.insert_row(&Row::from_string("1 al al@x.io")?)?;
+        table.flush()?;
+        drop(table);
+        let bytes = std::fs::read(&path).map_err(|e| e.to_string())?;
+
+        let mut table = Table::open_readonly(&path)?;
+        assert!(table.is_read_only());
+        let row = Row::from_string("2 bo bo@x.io")?;
+        assert!(matches!(table.insert_row(&row), Err(DbError::ReadOnly)));
+        assert!(matches!(table.upsert_row(&row), Err(DbError::ReadOnly)));
+        assert!(matches!(table.delete_where(|_| true), Err(DbError::ReadOnly)));
+        assert!(matches!(table.truncate(), Err(DbError::ReadOnly)));
+        assert!(matches!(table.set_encoding(StringEncoding::LengthPrefixed), Err(DbError::ReadOnly)));
+        assert_eq!(table.select_by_id(1)?, Some(Row::from_string("1 al al@x.io")?));
+        table.flush()?;
+        drop(table);
+        assert_eq!(std::fs::read(&path).map_err(|e| e.to_string())?, bytes);
+
+        assert!(matches!(Table::builder().read_only(true).build(), Err(DbError::InvalidConfig(_))));
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        assert!(Table::open_readonly(&path).is_err());
+        assert!(!path.exists());
+
+        Ok(())
     }
 
-    pub fn select_row(&self, position: usize) -> Option<Row> {
-        let (page_num, byte_offset_in_page) = Table::row_position(position);
-        if page_num >= self.pages.len() {
-            return None;
-        }
-        let page = self.pages.get(page_num).unwrap();
-        let bytes = &page[byte_offset_in_page..byte_offset_in_page + ROW_SIZE];
-        let row = Row::deserialize(bytes);
+    #[test]
+    fn drop_flushes() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-drop-flushes-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut table = Table::open(&path)?;
+        table.insert_row(&Row::from_string("1 al al@x.io")?)?;
+        drop(table);
+        let mut table = Table::open(&path)?;
+        assert_eq!(table.select_by_id(1)?, Some(Row::from_string("1 al al@x.io")?));
 
-        Some(row.unwrap())
+        // a table without changes since its last flush leaves the file alone when dropped
+        table.insert_row(&Row::from_string("2 bo bo@x.io")?)?;
+        table.flush()?;
+        std::fs::write(&path, b"overwritten").map_err(|e| e.to_string())?;
+        drop(table);
+        assert_eq!(std::fs::read(&path).map_err(|e| e.to_string())?, b"overwritten");
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
     }
-}
 
-pub struct TableIterator<'a> {
-    table: &'a Table,
-    position: usize,
-}
+    #[test]
+    fn pads_short_last_page_of_file() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-short-file-page-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
 
-impl<'a> Iterator for TableIterator<'a> {
-    type Item = Row;
+        // 20 rows leave 6 rows in the second page
+        let rows: Vec<Row> = (1..=20).map(|id| Row::from_string(&format!("{} user{} user{}@x.io", id, id, id))).collect::<Result<_, _>>()?;
+        let mut table = Table::open(&path)?;
+        table.insert_rows(&rows)?;
+        drop(table);
+        let full_len = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        let short_len = (HEADER_SIZE + PAGE_SIZE + 6 * ROW_SIZE) as u64;
+        let file = std::fs::OpenOptions::new().write(true).open(&path).map_err(|e| e.to_string())?;
+        file.set_len(short_len).map_err(|e| e.to_string())?;
 
-    fn next(&mut self) -> Option<Self::Item> {
-        if self.position >= self.table.num_rows() {
-            return None;
+        let table = Table::open(&path)?;
+        assert_eq!(table.num_pages(), 2);
+        assert_eq!(table.select_range(0, 20)?, rows);
+        drop(table);
+        // the padded page is written back along with its checksum
+        assert_eq!(std::fs::metadata(&path).map_err(|e| e.to_string())?.len(), full_len);
+        assert_eq!(Table::open(&path)?.select_range(0, 20)?, rows);
+
+        // cutting into a row loses data
+        file.set_len(short_len - 1).map_err(|e| e.to_string())?;
+        assert!(matches!(Table::open(&path), Err(DbError::TruncatedFile { .. })));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn file_lock() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-file-lock-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut table = Table::open(&path)?;
+        table.insert_row(&Row::from_string("1 al al@x.io")?)?;
+        table.flush()?;
+        assert!(matches!(Table::open(&path), Err(DbError::Locked)));
+        assert!(matches!(Table::open_readonly(&path), Err(DbError::Locked)));
+        drop(table);
+
+        // readers share the file, a writer has to wait for all of them
+        let reader = Table::open_readonly(&path)?;
+        let other_reader = Table::open_readonly(&path)?;
+        assert!(matches!(Table::open(&path), Err(DbError::Locked)));
+        drop((reader, other_reader));
+        assert_eq!(Table::open(&path)?.num_rows(), 1);
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn max_pages() -> Result<(), String> {
+        let mut table = Table::new().with_max_pages(2);
+        let row = Row::new(1, "foo", "bar@baz.com")?;
+        for _ in 0..2 * ROWS_PER_PAGE {
+            table.insert_row(&row)?;
         }
-        let row = self.table.select_row(self.position);
-        self.position += 1;
-        return row;
+        assert!(matches!(table.insert_row(&row), Err(DbError::CapacityExceeded)));
+
+        Ok(())
     }
-}
 
-impl Table {
-    pub fn iter(&self) -> TableIterator {
-        TableIterator {
-            table: self,
-            position: 0,
+    #[test]
+    fn rejected_insert_leaves_table_unchanged() -> Result<(), String> {
+        let mut table = Table::new();
+        let row = Row::new(1, "foo", "bar@baz.com")?;
+        for _ in 0..TABLE_MAX_PAGES * ROWS_PER_PAGE {
+            table.insert_row(&row)?;
+        }
+        assert_eq!(table.num_pages(), TABLE_MAX_PAGES);
+
+        let pages_before: Vec<_> = (0..table.num_pages()).map(|i| table.storage.page(i).unwrap().to_vec()).collect();
+
+        let other = Row::new(2, "baz", "bam@baz.com")?;
+        assert!(matches!(table.insert_row(&other), Err(DbError::CapacityExceeded)));
+
+        assert_eq!(table.num_rows(), TABLE_MAX_PAGES * ROWS_PER_PAGE);
+        assert_eq!(table.num_pages(), TABLE_MAX_PAGES);
+        for (i, page) in pages_before.iter().enumerate() {
+            assert_eq!(page, table.storage.page(i).unwrap());
         }
+
+        Ok(())
     }
-}
 
-impl<'a> IntoIterator for &'a Table {
-    type Item = Row;
-    type IntoIter = TableIterator<'a>;
+    #[test]
+    fn select_row_ref() -> Result<(), String> {
+        let mut table = Table::new();
+        let rows = [
+            Row::from_string("10 Andrew andre.jung@gmail.com")?,
+            Row::from_string("30 Birte birte.hochlander@web.de")?,
+        ];
+        table.insert_rows(&rows)?;
 
-    fn into_iter(self) -> Self::IntoIter {
-        TableIterator {
-            table: &self,
-            position: 0,
+        for (i, row) in rows.iter().enumerate() {
+            let row_ref = table.select_row_ref(i).unwrap();
+            assert_eq!(row_ref.id(), row.id);
+            assert_eq!(row_ref.username()?, row.username);
+            assert_eq!(row_ref.email()?, row.email);
+            assert_eq!(row_ref.to_row()?, table.select_row(i)?.unwrap());
+            assert_eq!(table.row_bytes(i), Some(row.serialize().as_slice()));
         }
+        assert!(table.select_row_ref(rows.len()).is_none());
+        assert!(table.row_bytes(rows.len()).is_none());
+
+        Ok(())
     }
-}
 
+    #[test]
+    fn select_corrupt_row() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::new(1, "foo", "bar@baz.com")?)?;
 
-#[cfg(test)]
-mod tests {
-    use rand::rngs::ThreadRng;
-    use rand::distributions::Alphanumeric;
-    use rand::Rng;
-    use crate::row::{Row, ROW_SIZE};
-    use crate::table::{ROWS_PER_PAGE, Table, TABLE_MAX_PAGES};
+        // a lone continuation byte is not valid UTF-8
+        table.storage.page_mut(0).unwrap()[USERNAME_OFFSET] = 0x80;
+
+        assert!(matches!(table.select_row(0), Err(DbError::InvalidRow(_))));
+
+        Ok(())
+    }
 
     #[test]
-    fn row_position() -> Result<(), String> {
-        assert_eq!(Table::row_position(0), (0, 0));
-        assert_eq!(Table::row_position(ROWS_PER_PAGE), (1, 0));
-        assert_eq!(Table::row_position(ROWS_PER_PAGE + 10), (1, 10 * ROW_SIZE));
-        assert_eq!(Table::row_position((TABLE_MAX_PAGES + 1) * ROWS_PER_PAGE), (TABLE_MAX_PAGES + 1, 0));
+    fn select_all_by_id() -> Result<(), String> {
+        let mut table = Table::new();
+        let rows = [
+            Row::from_string("7 alice alice@example.com")?,
+            Row::from_string("8 bob bob@example.com")?,
+            Row::from_string("7 carol carol@example.com")?,
+        ];
+        table.insert_rows(&rows)?;
+
+        assert_eq!(table.positions_of_id(7), [0, 2]);
+        let matches = table.select_all_by_id(7)?;
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0], rows[0]);
+        assert_eq!(matches[1], rows[2]);
+        assert!(table.select_all_by_id(9)?.is_empty());
 
         Ok(())
     }
 
     #[test]
-    fn insert_row_and_select() -> Result<(), String> {
+    fn upsert_row() -> Result<(), String> {
         let mut table = Table::new();
-        let row = Row {
-            id: 100,
-            username: String::from("Yz85rmUs0CzYJBDDA6hY38I07uOq6u2R"),
-            email: String::from("qopa@apoq.com"),
-        };
+        table.insert_row(&Row::from_string("1 alice alice@example.com")?)?;
 
-        table.insert_row(&row).expect("no error");
+        let bob = Row::from_string("2 bob bob@example.com")?;
+        assert_eq!(table.upsert_row(&bob)?, Upsert::Inserted(1));
+        assert_eq!(table.num_rows(), 2);
 
-        let page = table.pages.get(0);
-        assert!(page.is_some());
-        assert_eq!(table.num_rows(), 1);
+        let alice = Row::from_string("1 alice alice@example.org")?;
+        assert_eq!(table.upsert_row(&alice)?, Upsert::Replaced(0));
+        assert_eq!(table.num_rows(), 2);
+        assert_eq!(table.select_by_id(1)?.unwrap(), alice);
+        assert_eq!(table.select_all_by_id(1)?.len(), 1);
 
-        let row_from_table = table.select_row(0).unwrap();
-        assert_eq!(row, row_from_table);
+        Ok(())
+    }
+
+    #[test]
+    fn username_index() -> Result<(), String> {
+        let mut table = Table::new();
+        table.insert_row(&Row::new(1, "al", "al@x.io")?)?;
+        assert_eq!(table.positions_of_username("al"), None);
+        assert!(table.create_username_index()?);
+        assert!(!table.create_username_index()?);
+        assert_eq!(table.indexed_columns(), [ColumnName::Id, ColumnName::Username]);
+
+        table.insert_rows(&[Row::new(2, "bo", "bo@x.io")?, Row::new(3, "al", "al3@x.io")?, Row::new(4, "cy", "cy@x.io")?])?;
+        assert_eq!(table.positions_of_username("al"), Some(vec![0, 2]));
+        assert_eq!(table.upsert_row(&Row::new(2, "al", "al2@x.io")?)?, Upsert::Replaced(1));
+        assert_eq!(table.positions_of_username("al"), Some(vec![0, 1, 2]));
+        assert_eq!(table.positions_of_username("bo"), Some(vec![]));
+
+        // the last row moves into the slot of the deleted one
+        table.delete_row(0)?;
+        assert_eq!(table.positions_of_username("al"), Some(vec![1, 2]));
+        assert_eq!(table.positions_of_username("cy"), Some(vec![0]));
+        assert_eq!(table.delete_where(|row| row.username == "al")?, 2);
+        assert_eq!(table.positions_of_username("al"), Some(vec![]));
+        assert_eq!(table.select_row(0)?.map(|row| row.username), Some(String::from("cy")));
+
+        table.truncate()?;
+        assert_eq!(table.positions_of_username("cy"), Some(vec![]));
+        assert!(table.drop_username_index());
+        assert!(!table.drop_username_index());
+        assert_eq!(table.indexed_columns(), [ColumnName::Id]);
 
         Ok(())
     }
 
     #[test]
-    fn insert_and_select_multiple_rows() -> Result<(), String> {
-        let rows = [
-            Row::from_string("10 Andrew andre.jung@gmail.com")?,
-            Row::from_string("30 Birte birte.hochlander@web.de")?,
-            Row::from_string("20 Yanik yk@nomail.com")?,
-        ];
+    fn index_survives_reopen() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-index-reopen-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut table = Table::open(&path)?;
+        table.insert_rows(&[Row::from_string("5 a a@b.c")?, Row::from_string("6 b b@c.d")?, Row::from_string("5 c c@d.e")?])?;
+        table.flush()?;
+        drop(table);
+
+        let table = Table::open(&path)?;
+        assert_eq!(table.positions_of_id(5), [0, 2]);
+        assert_eq!(table.select_by_id(6)?.unwrap().username, "b");
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn snapshot_and_restore() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-snapshot-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let rows: Vec<Row> = (0..(ROWS_PER_PAGE as u32 + 3))
+            .map(user_row)
+            .collect();
+        let mut table = Table::open(&path)?;
+        table.insert_rows(&rows)?;
+
+        let blob = table.snapshot();
+        let restored = Table::restore(&blob)?;
+        assert_eq!(restored.num_rows(), table.num_rows());
+        assert_eq!(restored.num_pages(), table.num_pages());
+        assert_eq!(restored.iter().collect::<Vec<Row>>(), rows);
+        assert_eq!(restored.positions_of_id(3), [3]);
+        assert_eq!(restored.snapshot(), blob);
+        assert!(restored.path().is_none());
+
+        // the snapshot did not touch the file, which is still empty
+        assert_eq!(std::fs::metadata(&path).map_err(|e| e.to_string())?.len(), 0);
+
+        // a damaged image is rejected
+        let mut damaged = blob;
+        let len = damaged.len();
+        damaged[len - 100] ^= 0x01;
+        assert!(matches!(Table::restore(&damaged), Err(DbError::Corruption { page: 1 })));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
 
+    #[test]
+    fn delete_row() -> Result<(), String> {
         let mut table = Table::new();
-        for row in rows.iter() {
-            table.insert_row(row)?;
+        let rows: Vec<Row> = (1..=(ROWS_PER_PAGE as u32 + 1))
+            .map(user_row)
+            .collect();
+        table.insert_rows(&rows)?;
+        assert_eq!(table.num_pages(), 2);
+
+        // the last row moves into the slot of the deleted one and the emptied page is dropped
+        table.delete_row(1)?;
+        assert_eq!(table.num_rows(), rows.len() - 1);
+        assert_eq!(table.num_pages(), 1);
+        assert_eq!(table.select_row(1)?.unwrap(), rows[rows.len() - 1]);
+        assert!(table.select_all_by_id(2)?.is_empty());
+        assert_eq!(table.positions_of_id(rows.len() as u32), [1]);
+
+        assert!(table.delete_row(table.num_rows()).is_err());
+        assert_eq!(table.delete_where(|row| row.id % 2 == 0)?, ROWS_PER_PAGE / 2 - 1);
+        assert!(table.iter().all(|row| row.id % 2 == 1));
+        for row in table.iter() {
+            assert_eq!(table.select_by_id(row.id)?.unwrap(), row);
         }
 
-        for i in 0..table.num_rows() {
-            let table_row = table.select_row(i).unwrap();
-            assert_eq!(rows[i], table_row);
+        Ok(())
+    }
+
+    #[test]
+    fn compact_into() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-compact-{}.db", std::process::id()));
+        let copy_path = std::env::temp_dir().join(format!("dbrs-compacted-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let _ = std::fs::remove_file(&copy_path);
+
+        let mut table = Table::builder().path(&path).page_size(1024).build()?;
+        let mut ids: Vec<u32> = (1..=20).collect();
+        ids.shuffle(&mut rand::thread_rng());
+        for &id in &ids {
+            table.insert_row(&Row::new(id, format!("user{}", id), format!("user{}@example.com", id))?)?;
         }
+        table.delete_where(|row| row.id % 3 == 0)?;
+        table.set_encoding(StringEncoding::LengthPrefixed)?;
+        table.flush()?;
+        let original = std::fs::read(&path).map_err(|e| e.to_string())?;
 
+        assert_eq!(table.compact_into(&copy_path)?, 14);
+        assert_eq!(std::fs::read(&path).map_err(|e| e.to_string())?, original);
+        let expected = table.positions_by_id()
+            .filter_map(|position| table.select_row(position).transpose())
+            .collect::<Result<Vec<Row>, _>>()?;
+        assert!(expected.windows(2).all(|pair| pair[0].id < pair[1].id));
+
+        let compacted = Table::open(&copy_path)?;
+        assert_eq!(compacted.select_range(0, usize::MAX)?, expected);
+        assert_eq!(compacted.encoding(), StringEncoding::LengthPrefixed);
+        assert_eq!(compacted.page_size(), 1024);
+        assert_eq!(compacted.num_pages(), 5);
+
+        // an existing file is never overwritten
+        assert!(matches!(table.compact_into(&copy_path), Err(DbError::Io(_))));
+        drop(compacted);
+        std::fs::remove_file(&copy_path).map_err(|e| e.to_string())?;
+        drop(table);
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
         Ok(())
     }
 
+    #[test]
+    fn capacity_remaining() -> Result<(), String> {
+        let mut table = Table::new().with_max_pages(1);
+        assert_eq!(table.capacity_remaining(), ROWS_PER_PAGE);
+        for id in 1..=ROWS_PER_PAGE {
+            table.insert_row(&Row::new(id as u32, "al", "al@x.io")?)?;
+            assert_eq!(table.capacity_remaining(), ROWS_PER_PAGE - id);
+        }
+        assert!(matches!(table.insert_row(&Row::new(0, "al", "al@x.io")?), Err(DbError::CapacityExceeded)));
+        table.delete_row(0)?;
+        assert_eq!(table.capacity_remaining(), 1);
+
+        assert_eq!(Table::new().with_max_pages(usize::MAX).capacity_remaining(), usize::MAX);
 
-    fn gen_random_string(rng: &mut ThreadRng, max_len: usize) -> String {
-        let random_len = rng.gen_range(1..=max_len);
-        rng.sample_iter(&Alphanumeric)
-            .take(random_len)
-            .map(char::from)
-            .collect::<String>()
+        Ok(())
     }
 
     #[test]
-    fn insert_and_select_lots_of_rows() -> Result<(), String> {
-        use crate::row::{EMAIL_SIZE, USERNAME_SIZE};
+    fn disk_usage() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-disk-usage-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
 
-        let mut rng = rand::thread_rng();
-        let num_rows = 1000;
-        let mut rows: Vec<Row> = Vec::new();
-        let mut table = Table::new();
+        let mut table = Table::open(&path)?;
+        assert_eq!(table.disk_usage(), DiskUsage { total_bytes: HEADER_SIZE, live_bytes: 0, dead_bytes: 0 });
+        assert_eq!(table.disk_usage().fragmentation(), 0.0);
 
-        for _i in 0..num_rows {
-            let random_id = rng.gen();
-            let random_name = gen_random_string(&mut rng, USERNAME_SIZE);
-            let random_email = gen_random_string(&mut rng, EMAIL_SIZE);
+        let rows: Vec<Row> = (0..(2 * ROWS_PER_PAGE as u32))
+            .map(user_row)
+            .collect();
+        table.insert_rows(&rows)?;
+        table.delete_where(|row| row.id % 5 == 0)?;
+        table.flush()?;
 
-            let row = Row { id: random_id, username: random_name, email: random_email };
+        let usage = table.disk_usage();
+        assert_eq!(usage.total_bytes as u64, std::fs::metadata(&path).map_err(|e| e.to_string())?.len());
+        assert_eq!(usage.live_bytes, table.num_rows() * ROW_SIZE);
+        assert!(usage.dead_bytes >= 6 * ROW_SIZE);
+        assert_eq!(usage.live_bytes + usage.dead_bytes, 2 * PAGE_DATA_SIZE);
+        assert!(usage.fragmentation() > 20.0 && usage.fragmentation() < 25.0);
 
-            table.insert_row(&row)?;
-            rows.push(row);
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn deleting_a_page_of_rows_releases_it() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-release-page-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let rows: Vec<Row> = (0..(3 * ROWS_PER_PAGE as u32))
+            .map(user_row)
+            .collect();
+
+        let mut table = Table::open(&path)?;
+        table.insert_rows(&rows)?;
+        table.flush()?;
+        let file_len = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+
+        // deletes never leave dead rows behind, so emptying a page's worth of rows frees the last page
+        assert_eq!(table.delete_where(|row| (row.id as usize) < ROWS_PER_PAGE)?, ROWS_PER_PAGE);
+        assert_eq!(table.num_pages(), 2);
+        table.flush()?;
+        let shrunk_len = std::fs::metadata(&path).map_err(|e| e.to_string())?.len();
+        assert_eq!(file_len - shrunk_len, table.page_size() as u64);
+        drop(table);
+        assert_eq!(Table::open(&path)?.num_pages(), 2);
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn positions_by_id_after_delete_and_reinsert() -> Result<(), String> {
+        let mut table = Table::new();
+        for id in [5, 1, 4, 2, 3] {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
         }
+        table.delete_where(|row| row.id == 1)?;
+        table.insert_row(&Row::new(1, "foo", "bar@baz.com")?)?;
+
+        let ids: Vec<u32> = table.iter().map(|row| row.id).collect();
+        assert_eq!(ids, [5, 3, 4, 2, 1]);
+        let ids: Vec<u32> = table.positions_by_id().map(|position| table.select_row(position).unwrap().unwrap().id).collect();
+        assert_eq!(ids, [1, 2, 3, 4, 5]);
 
-        for i in 0..table.num_rows() {
-            let table_row = table.select_row(i).unwrap();
-            assert_eq!(rows[i], table_row);
+        Ok(())
+    }
+
+    #[test]
+    fn next_placement() -> Result<(), String> {
+        let mut table = Table::new();
+        let row = Row::new(1, "foo", "bar@baz.com")?;
+        assert_eq!(table.next_placement(), Placement { page_num: 0, byte_offset: 0, new_page: true });
+
+        for n in 1..=2 * ROWS_PER_PAGE {
+            table.insert_row(&row)?;
+            let placement = table.next_placement();
+            // only the insert after a full page needs a new one
+            assert_eq!(placement.new_page, n % ROWS_PER_PAGE == 0);
+            assert_eq!((placement.page_num, placement.byte_offset), table.row_position(n));
         }
 
         Ok(())
     }
 
+    #[test]
+    fn reserved_zero_id() -> Result<(), String> {
+        let row = Row::new(0, "foo", "bar@baz.com")?;
+
+        let mut table = Table::new().with_reserved_zero_id();
+        assert!(matches!(table.insert_row(&row), Err(DbError::InvalidId(0))));
+        assert!(matches!(table.upsert_row(&row), Err(DbError::InvalidId(0))));
+        let rows = [Row::from_string("1 foo bar@baz.com")?, row];
+        assert!(matches!(table.insert_rows(&rows), Err(DbError::InvalidId(0))));
+        assert_eq!(table.num_rows(), 0);
+
+        // id 0 is a regular id unless reserved
+        assert_eq!(Table::new().insert_row(&rows[1])?, 0);
+
+        Ok(())
+    }
 
     #[test]
-    fn test_iterator() -> Result<(), String> {
+    fn stats() -> Result<(), String> {
         let mut table = Table::new();
-        let row1 = Row { id: 100, username: "foo".to_string(), email: "bar".to_string() };
-        let row2 = Row { id: 200, username: "baz".to_string(), email: "bam".to_string() };
+        assert_eq!(table.bytes_used(), 0);
+        assert_eq!(table.last_page_free_bytes(), 0);
+        assert_eq!(table.last_page_fill(), 0);
 
-        table.insert_row(&row1)?;
-        table.insert_row(&row2)?;
+        for id in 0..(ROWS_PER_PAGE as u32 + 1) {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
+        }
+        assert_eq!(table.bytes_used(), (ROWS_PER_PAGE + 1) * ROW_SIZE);
+        assert_eq!(table.last_page_free_bytes(), PAGE_DATA_SIZE - ROW_SIZE);
+        assert_eq!(table.last_page_fill(), ROW_SIZE);
+        assert_eq!(table.rows_per_page(), 14);
+        assert_eq!(table.rows_per_page(), PAGE_DATA_SIZE / ROW_SIZE);
 
-        let mut iter = table.iter();
-        assert_eq!(iter.next().unwrap(), row1);
-        assert_eq!(iter.next().unwrap(), row2);
-        assert!(iter.next().is_none());
+        // a header counting fewer rows than the pages hold
+        let mut storage = MemoryStorage::new(PAGE_SIZE);
+        storage.page_mut(0).unwrap();
+        storage.page_mut(1).unwrap();
+        storage.flush(Header::new(1)).map_err(|e| e.to_string())?;
+        let table = Table::with_storage(Box::new(storage));
+        assert_eq!(table.num_pages(), 2);
+        assert_eq!(table.last_page_fill(), ROW_SIZE);
+        assert_eq!(table.last_page_free_bytes(), PAGE_DATA_SIZE - ROW_SIZE);
 
         Ok(())
     }
 
     #[test]
-    fn test_into_iterator() -> Result<(), String> {
-        let mut table = Table::new();
+    fn truncate() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-truncate-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
 
-        // empty table
-        for _ in &table {
-            assert!(false);
+        let mut table = Table::open(&path)?;
+        for id in 0..(ROWS_PER_PAGE as u32 * 2) {
+            table.insert_row(&Row::new(id, "foo", "bar@baz.com")?)?;
         }
+        table.flush()?;
 
-        // table with two items
-        let rows = [Row { id: 100, username: "foo".to_string(), email: "bar".to_string() },
-            Row { id: 200, username: "baz".to_string(), email: "bam".to_string() }];
+        table.truncate()?;
+        assert_eq!(table.num_rows(), 0);
+        assert_eq!(table.num_pages(), 0);
+        assert!(table.iter().next().is_none());
+        assert_eq!(std::fs::metadata(&path).map_err(|e| e.to_string())?.len(), 0);
 
-        table.insert_row(&rows[0])?;
-        table.insert_row(&rows[1])?;
+        let row = Row::new(7, "baz", "bam@baz.com")?;
+        assert_eq!(table.insert_row(&row)?, 0);
+        assert_eq!(table.select_row(0)?.unwrap(), row);
 
-        let mut i = 0;
-        for r in &table {
-            assert_eq!(r, rows[i]);
-            i += 1;
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn open_rejects_foreign_file() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-foreign-file-{}.db", std::process::id()));
+        std::fs::write(&path, [b'x'; 100]).map_err(|e| e.to_string())?;
+
+        assert!(matches!(Table::open(&path), Err(DbError::BadMagic)));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn flush_and_reopen() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-flush-and-reopen-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let rows: Vec<Row> = (0..(ROWS_PER_PAGE as u32 + 3))
+            .map(user_row)
+            .collect();
+
+        let mut table = Table::open(&path)?;
+        assert_eq!(table.num_rows(), 0);
+        for row in rows.iter() {
+            table.insert_row(row)?;
+        }
+        table.flush()?;
+        drop(table);
+
+        let table = Table::open(&path)?;
+        assert_eq!(table.num_rows(), rows.len());
+        assert_eq!(table.num_pages(), 2);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(*row, table.select_row(i)?.unwrap());
         }
 
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    #[test]
+    fn length_prefixed_strings() -> Result<(), String> {
+        let path = std::env::temp_dir().join(format!("dbrs-length-prefixed-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let plain = Row::new(1, "foo", "foo@bar.com")?;
+        let with_nul = Row::new(2, "nul\0name", "a\0b@c.d")?;
+
+        let mut table = Table::open(&path)?;
+        table.insert_row(&plain)?;
+        table.set_encoding(StringEncoding::LengthPrefixed)?;
+        assert_eq!(table.select_row(0)?.unwrap(), plain);
+        table.insert_row(&with_nul)?;
+        assert_eq!(table.select_row(1)?.unwrap(), with_nul);
+        assert_eq!(table.select_row_ref(1).unwrap().username()?, "nul\0name");
+        table.flush()?;
+        drop(table);
+
+        // the encoding is stored in the file
+        let mut table = Table::open(&path)?;
+        assert_eq!(table.encoding(), StringEncoding::LengthPrefixed);
+        assert_eq!(table.iter().collect::<Vec<Row>>(), [plain, with_nul]);
+
+        // switching back cuts the strings at the embedded NUL
+        table.set_encoding(StringEncoding::NulTerminated)?;
+        assert_eq!(table.select_row(1)?.unwrap().username, "nul");
+
+        // a length-prefixed string fits in one byte less than its slot, and is never truncated
+        let full = Row::new(3, "é".repeat(USERNAME_SIZE / 2), "al@x.io")?;
+        table.insert_row(&full)?;
+        assert!(matches!(table.set_encoding(StringEncoding::LengthPrefixed), Err(DbError::InvalidRow(_))));
+        assert_eq!(table.encoding(), StringEncoding::NulTerminated);
+        assert_eq!(table.select_row(2)?.unwrap(), full);
+        table.delete_row(2)?;
+        table.set_encoding(StringEncoding::LengthPrefixed)?;
+        assert!(matches!(table.insert_row(&full), Err(DbError::InvalidRow(_))));
+        assert!(matches!(table.insert_rows(&[user_row(4), full]), Err(DbError::InvalidRow(_))));
+        assert_eq!(table.num_rows(), 2);
+
+        let longest = Row::new(3, "é".repeat(USERNAME_SIZE / 2 - 1) + "a", "al@x.io")?;
+        assert_eq!(longest.username.len(), USERNAME_SIZE - 1);
+        table.insert_row(&longest)?;
+        assert_eq!(table.select_row(2)?.unwrap(), longest);
+        assert_eq!(table.iter().nth(2), Some(longest));
+
+        std::fs::remove_file(&path).map_err(|e| e.to_string())?;
         Ok(())
     }
-}
\ No newline at end of file
+}
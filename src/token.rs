@@ -0,0 +1,72 @@
+use std::fmt;
+
+/// A whitespace-separated word of an input line and where it starts
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Token<'a> {
+    pub text: &'a str,
+    /// 1-based number of the token in the line
+    pub index: usize,
+    /// Byte offset of the first char in the line
+    pub offset: usize,
+    /// 1-based column of the first char in the line
+    pub column: usize,
+}
+
+impl fmt::Display for Token<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "'{}' at token {}, column {}", self.text, self.index, self.column)
+    }
+}
+
+/// Splits `s` at whitespace like `split_whitespace`, keeping track of the positions of the words
+pub fn tokenize(s: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    let mut column = 0;
+    let mut start_column = 0;
+    for (offset, c) in s.char_indices().chain(std::iter::once((s.len(), ' '))) {
+        column += 1;
+        match (start, c.is_whitespace()) {
+            (None, false) => {
+                start = Some(offset);
+                start_column = column;
+            }
+            (Some(begin), true) => {
+                tokens.push(Token { text: &s[begin..offset], index: tokens.len() + 1, offset: begin, column: start_column });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    tokens
+}
+
+/// Tells whether the texts of the first tokens are the `keywords`, ignoring case
+pub fn starts_with_keywords(tokens: &[Token], keywords: &[&str]) -> bool {
+    tokens.len() >= keywords.len()
+        && tokens.iter().zip(keywords).all(|(token, keyword)| token.text.eq_ignore_ascii_case(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::token::{starts_with_keywords, tokenize, Token};
+
+    #[test]
+    fn positions() {
+        let tokens = tokenize("  insert  1 \u{e9} x");
+        let texts: Vec<&str> = tokens.iter().map(|token| token.text).collect();
+        assert_eq!(texts, ["insert", "1", "\u{e9}", "x"]);
+        assert_eq!(tokens[0], Token { text: "insert", index: 1, offset: 2, column: 3 });
+        assert_eq!(tokens[3], Token { text: "x", index: 4, offset: 15, column: 15 });
+        assert_eq!(tokens[1].to_string(), "'1' at token 2, column 11");
+        assert!(tokenize(" \t ").is_empty());
+    }
+
+    #[test]
+    fn keywords() {
+        let tokens = tokenize("insert OR replace 1");
+        assert!(starts_with_keywords(&tokens[1..], &["or", "replace"]));
+        assert!(!starts_with_keywords(&tokens, &["or", "replace"]));
+        assert!(!starts_with_keywords(&tokens[3..], &["or", "replace"]));
+    }
+}
@@ -0,0 +1,73 @@
+//! Spans and events of table operations for embedders collecting structured logs, enabled by the `trace`
+//! feature. Without the feature the `trace_span!` and `trace_event!` macros expand to nothing.
+//!
+//! The operations report to the `Subscriber` installed with `set_subscriber`, nothing is recorded until
+//! one is installed:
+//!
+//! - spans `insert_row` (`id`), `select_by_id` (`id`), `open` (`path`) and `flush` (`num_pages`)
+//! - events `load_pages` (`num_pages`) when a file is read and `drop_pages` (`num_pages`) when pages
+//!   emptied by deletes are released. Every page of a table is held in memory, so pages are never
+//!   evicted otherwise.
+//! - event `flush_failed` (`error`) when dropping a table could not write its changes
+use std::fmt::Display;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+static SUBSCRIBER: OnceLock<Box<dyn Subscriber>> = OnceLock::new();
+
+/// The name of a span or event and its fields, formatted with `Display`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: &'static str,
+    pub fields: Vec<(&'static str, String)>,
+}
+
+/// Receives the spans and events of all tables. The methods do nothing by default.
+pub trait Subscriber: Send + Sync {
+    fn enter(&self, _span: &Record) {}
+
+    /// The span ended after `elapsed`
+    fn exit(&self, _span: &Record, _elapsed: Duration) {}
+
+    fn event(&self, _event: &Record) {}
+}
+
+/// Installs the subscriber for the rest of the process. Fails with the given subscriber if one is
+/// installed already.
+pub fn set_subscriber(subscriber: Box<dyn Subscriber>) -> Result<(), Box<dyn Subscriber>> {
+    SUBSCRIBER.set(subscriber)
+}
+
+/// A span that has been entered, it is exited when dropped
+#[derive(Debug)]
+pub struct SpanGuard {
+    record: Record,
+    start: Instant,
+}
+
+impl Drop for SpanGuard {
+    fn drop(&mut self) {
+        if let Some(subscriber) = SUBSCRIBER.get() {
+            subscriber.exit(&self.record, self.start.elapsed());
+        }
+    }
+}
+
+/// Enters a span, `None` if there is no subscriber. Use `trace_span!` instead.
+pub fn enter_span(name: &'static str, fields: &[(&'static str, &dyn Display)]) -> Option<SpanGuard> {
+    let subscriber = SUBSCRIBER.get()?;
+    let record = record(name, fields);
+    subscriber.enter(&record);
+    Some(SpanGuard { record, start: Instant::now() })
+}
+
+/// Reports an event to the subscriber, if any. Use `trace_event!` instead.
+pub fn event(name: &'static str, fields: &[(&'static str, &dyn Display)]) {
+    if let Some(subscriber) = SUBSCRIBER.get() {
+        subscriber.event(&record(name, fields));
+    }
+}
+
+fn record(name: &'static str, fields: &[(&'static str, &dyn Display)]) -> Record {
+    Record { name, fields: fields.iter().map(|(key, value)| (*key, value.to_string())).collect() }
+}
@@ -0,0 +1,26 @@
+use rand::distributions::Alphanumeric;
+use rand::Rng;
+
+use crate::row::{Row, EMAIL_SIZE, USERNAME_SIZE};
+
+/// A random alphanumeric string of 1 to `max_len` characters
+pub fn gen_random_string<R: Rng>(rng: &mut R, max_len: usize) -> String {
+    let random_len = rng.gen_range(1..=max_len);
+    rng.sample_iter(&Alphanumeric)
+        .take(random_len)
+        .map(char::from)
+        .collect::<String>()
+}
+
+/// A row with a random username and a random email `<name>@<domain>`, both fitting their slots
+pub fn gen_random_row<R: Rng>(rng: &mut R, id: u32) -> Row {
+    let username = gen_random_string(rng, USERNAME_SIZE);
+    let name = gen_random_string(rng, (EMAIL_SIZE - 1) / 2);
+    let domain = gen_random_string(rng, EMAIL_SIZE - 1 - name.len());
+    Row::new(id, username, format!("{}@{}", name, domain)).expect("random fields fit their slots")
+}
+
+/// The row `(id, user<id>, user<id>@example.com)`, for tests and benchmarks
+pub fn user_row(id: u32) -> Row {
+    Row::new(id, format!("user{}", id), format!("user{}@example.com", id)).expect("user rows fit their slots")
+}
@@ -1,43 +1,418 @@
 use std::error::Error;
-use std::io::Write;
-use assert_cmd::prelude::*;
-use predicates::prelude::*;
-use std::process::{Command, Stdio};
-
-// #[test]
-// fn start_db() -> Result<(), Box<dyn Error>> {
-//     let mut cmd = Command::cargo_bin("dbrs")?.spawn()?;
-//
-//     // Wait for the child process to finish
-//     let output = cmd.wait_with_output().unwrap();
-//
-//     println!("{:?}", output);
-//
-//
-//     Ok(())
-// }
-
-#[test]
-fn test_dbrs() -> Result<(), Box<dyn Error>> {
-    let mut child = Command::new("cargo")
-        .args(&["run", "--bin", "dbrs"])
-        .stdin(Stdio::piped())
-        .stdout(Stdio::piped())
-        .spawn()
-        .unwrap();
-
-    let input = ".exit";
-    let expected_output = String::from("db> Exiting...\n");
-
-    // Write the input to the stdin of the child process
-    child.stdin.as_mut().unwrap().write_all(input.as_bytes()).unwrap();
-
-    // Wait for the child process to finish
-    let output = child.wait_with_output().unwrap();
-    let str_output = String::from_utf8(output.stdout)?;
-
-    // Check that the output of the child process matches the expected output
-    assert_eq!(str_output, expected_output);
-
-    Ok(())
-}
\ No newline at end of file
+use std::process::Output;
+use std::time::Duration;
+
+/// Runs dbrs with the given arguments and `input` piped to stdin. The process is killed if it does not
+/// exit on its own within a few seconds.
+fn run(args: &[&str], input: &str) -> Output {
+    assert_cmd::Command::cargo_bin("dbrs")
+        .unwrap()
+        .args(args)
+        .write_stdin(input)
+        .timeout(Duration::from_secs(10))
+        .output()
+        .unwrap()
+}
+
+/// Runs the REPL on a script and returns everything it printed to stdout
+fn run_script(input: &str) -> String {
+    let output = run(&[], input);
+    assert!(output.status.success(), "dbrs failed: {:?}", output);
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn start_db() {
+    // nothing but an immediate EOF
+    assert_eq!(run_script(""), "db> \n");
+}
+
+#[test]
+fn test_dbrs() {
+    assert_eq!(run_script(".exit"), "db> Exiting...\n");
+}
+
+#[test]
+fn exits_on_eof() {
+    // no trailing .exit, the process has to stop on its own once stdin is closed
+    let stdout = run_script("insert 1 foo foo@bar.com\nselect\n");
+    assert_eq!(stdout, "db> Row inserted at position 0\ndb> (1, foo, foo@bar.com)\ndb> \n");
+}
+
+#[test]
+fn strict_mode_exits_on_error() {
+    assert_eq!(run(&["--strict"], "insert 1 foo\nselect\n").status.code(), Some(1));
+
+    // without --strict the REPL reports the error and keeps going
+    assert!(run(&[], "insert 1 foo\nselect\n").status.success());
+}
+
+#[test]
+fn open_switches_database() -> Result<(), Box<dyn Error>> {
+    let dir = std::env::temp_dir();
+    let first = dir.join(format!("dbrs-open-first-{}.db", std::process::id()));
+    let second = dir.join(format!("dbrs-open-second-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&first);
+    let _ = std::fs::remove_file(&second);
+
+    let stdout = run_script(&format!(
+        ".open {first}\ninsert 1 alice alice@example.com\n.open {second}\nselect\n\
+         insert 2 bob bob@example.com\ninsert 3 carol carol@example.com\n.open {first}\nselect\n.close\nselect\n.exit\n",
+        first = first.display(),
+        second = second.display(),
+    ));
+
+    assert!(stdout.contains(&format!("Opened {} with 0 rows", second.display())));
+    assert!(stdout.contains(&format!("Opened {} with 1 rows", first.display())));
+    assert_eq!(stdout.matches("(1, alice, alice@example.com)").count(), 1);
+    assert!(!stdout.contains("bob"));
+
+    // the second file kept its rows after switching away from it
+    assert!(run_script(&format!(".open {}\n", second.display())).contains("with 2 rows"));
+
+    std::fs::remove_file(&first)?;
+    std::fs::remove_file(&second)?;
+    Ok(())
+}
+
+#[test]
+fn import_file() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("dbrs-import-{}.txt", std::process::id()));
+    std::fs::write(&path, "insert 1 alice alice@example.com\n\n2 bob bob@example.com\nINSERT 3 carol carol@example.com\n")?;
+
+    let stdout = run_script(&format!(".import {}\nselect\n", path.display()));
+    assert!(stdout.contains("Imported 3 rows"));
+    assert!(stdout.contains("(1, alice, alice@example.com)\n"));
+    assert!(stdout.contains("(2, bob, bob@example.com)\n"));
+    assert!(stdout.contains("(3, carol, carol@example.com)\n"));
+
+    // a malformed line is reported and nothing gets inserted
+    std::fs::write(&path, "1 alice alice@example.com\n2 bob\n")?;
+    let output = run(&[], &format!(".import {}\nselect\n", path.display()));
+    assert!(String::from_utf8(output.stderr)?.contains("line 2"));
+    assert!(!String::from_utf8(output.stdout)?.contains("alice"));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn stats() {
+    let stdout = run_script("insert 1 alice alice@example.com\ninsert 2 bob bob@example.com\n.stats\n");
+    assert!(stdout.contains("num_rows: 2\nnum_pages: 1\nbytes_used: 582\nlast_page_free_bytes: 3510\n"));
+    assert!(stdout.contains("file_bytes: 4128\ndead_bytes: 3510\nfragmentation: 85.8%\ncapacity_remaining: 1398\n"));
+}
+
+#[test]
+fn select_by_id_lists_duplicates() {
+    let stdout = run_script("insert 7 alice alice@example.com\ninsert 8 bob bob@example.com\ninsert 7 carol carol@example.com\n\
+                             select where id = 7\n");
+    assert!(stdout.contains("db> 0: (7, alice, alice@example.com)\n2: (7, carol, carol@example.com)\ndb> "));
+}
+
+#[test]
+fn timer() {
+    let stdout = run_script("insert 1 alice alice@example.com\n.timer on\nselect\n.timer off\nselect\n");
+    assert_eq!(stdout.matches("Run Time: ").count(), 1);
+    assert!(stdout.contains("(1, alice, alice@example.com)\nRun Time: "));
+    assert!(stdout.contains(" ms\n"));
+}
+
+#[test]
+fn comments_are_skipped() {
+    let output = run(&["--strict"], "-- seed data\ninsert 1 alice alice@example.com -- the first user\nselect\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "db> db> Row inserted at position 0\ndb> (1, alice, alice@example.com)\ndb> \n");
+}
+
+#[test]
+fn count_group_by() {
+    let stdout = run_script("insert 1 bob b1@x.io\ninsert 2 al a@x.io\ninsert 3 bob b2@x.io\ninsert 4 cy c@x.io\ninsert 5 bob b3@x.io\n\
+                             select username, count(*) group by username\n");
+    assert!(stdout.ends_with("db> (al, 1)\n(bob, 3)\n(cy, 1)\ndb> \n"));
+}
+
+#[test]
+fn blank_lines_are_skipped() {
+    let output = run(&["--strict"], "\n   \n\t\ninsert 1 alice alice@example.com\n\nselect\n");
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "db> db> db> db> Row inserted at position 0\ndb> db> (1, alice, alice@example.com)\ndb> \n");
+}
+
+#[test]
+fn meta_commands_ignore_case() -> Result<(), Box<dyn Error>> {
+    assert_eq!(run_script(" .EXIT "), "db> Exiting...\n");
+    assert!(run_script(".Help\n").contains(".open <path>"));
+
+    // the case of the argument is kept
+    let path = std::env::temp_dir().join(format!("dbrs-MixedCase-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    assert!(run_script(&format!(".OPEN {}\n", path.display())).contains(&format!("Opened {} with 0 rows", path.display())));
+    assert!(path.exists());
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn pagesize_ignored_without_terminal() {
+    let inserts: String = (1..=5).map(|id| format!("insert {} u{} u{}@x.io\n", id, id, id)).collect();
+    let stdout = run_script(&format!("{}.pagesize 2\nselect\n", inserts));
+    assert!(!stdout.contains("-- More"));
+    assert_eq!(stdout.matches("@x.io)").count(), 5);
+}
+
+#[test]
+fn aggregates() {
+    assert_eq!(run_script("select max(id)\n"), "db> NULL\ndb> \n");
+    let stdout = run_script("insert 3 a a@x.io\ninsert 6 b b@x.io\nselect min(id)\nselect max(id)\nselect avg(id)\n");
+    assert!(stdout.ends_with("db> 3\ndb> 6\ndb> 4.5\ndb> \n"));
+}
+
+#[test]
+fn backup_and_restore() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("dbrs-backup-{}.db", std::process::id()));
+
+    let stdout = run_script(&format!(
+        "insert 1 alice alice@example.com\n.backup {path}\ninsert 2 bob bob@example.com\n.restore {path}\nselect\n",
+        path = path.display(),
+    ));
+    assert!(stdout.contains("Backed up 1 rows"));
+    assert!(stdout.contains("Restored 1 rows"));
+    assert!(stdout.ends_with("db> (1, alice, alice@example.com)\ndb> \n"));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn select_orders_by_id() {
+    let stdout = run_script("insert 3 c c@x.io\ninsert 1 a a@x.io\ninsert 2 b b@x.io\ndelete where id = 1\ninsert 1 a a@x.io\n\
+                             select\nselect physical\n");
+    assert!(stdout.contains("Deleted 1 rows"));
+    assert!(stdout.contains("db> (1, a, a@x.io)\n(2, b, b@x.io)\n(3, c, c@x.io)\n"));
+    assert!(stdout.contains("db> (3, c, c@x.io)\n(2, b, b@x.io)\n(1, a, a@x.io)\n"));
+}
+
+#[test]
+fn delete_id_range() {
+    let stdout = run_script("insert 3 c c@x.io\ninsert 1 a a@x.io\ninsert 5 e e@x.io\ninsert 4 d d@x.io\ninsert 2 b b@x.io\n\
+                             delete where id > 3\nselect\ndelete where id >= 2 and id < 3\nselect\ndelete where id < 0\n");
+    assert!(stdout.contains("db> Deleted 2 rows\ndb> (1, a, a@x.io)\n(2, b, b@x.io)\n(3, c, c@x.io)\n"));
+    assert!(stdout.contains("db> Deleted 1 rows\ndb> (1, a, a@x.io)\n(3, c, c@x.io)\n"));
+    assert!(stdout.ends_with("db> Deleted 0 rows\ndb> \n"));
+}
+
+#[test]
+fn select_first_and_last() {
+    assert_eq!(run_script("select first\n"), "db> No rows\ndb> \n");
+    let stdout = run_script("insert 3 c c@x.io\ninsert 1 a a@x.io\ninsert 7 g g@x.io\ninsert 2 b b@x.io\nselect first\nselect LAST\n");
+    assert!(stdout.ends_with("db> (1, a, a@x.io)\ndb> (7, g, g@x.io)\ndb> \n"));
+}
+
+#[test]
+fn select_id_range() {
+    let stdout = run_script("insert 3 c c@x.io\ninsert 1 a a@x.io\ninsert 2 b b@x.io\nselect where id >= 2\nselect where id != 2\n");
+    assert!(stdout.contains("db> (2, b, b@x.io)\n(3, c, c@x.io)\ndb> "));
+    assert!(stdout.ends_with("db> (1, a, a@x.io)\n(3, c, c@x.io)\ndb> \n"));
+}
+
+#[test]
+fn echo() {
+    let stdout = run_script(".echo on\ninsert 1 a a@x.io\n  select  \n.echo off\nselect\n");
+    assert_eq!(
+        stdout,
+        "db> db> -- insert 1 a a@x.io\nRow inserted at position 0\ndb> -- select\n(1, a, a@x.io)\ndb> -- .echo off\ndb> (1, a, a@x.io)\ndb> \n"
+    );
+}
+
+#[test]
+fn verbose_reports_placement() {
+    let stdout = run_script(".verbose on\ninsert 1 a a@x.io\ninsert 2 b b@x.io\n");
+    assert!(stdout.contains("Storing row in new page 0 at byte offset 0\nRow inserted at position 0\n"));
+    assert!(stdout.contains("Storing row in page 0 at byte offset 291\nRow inserted at position 1\n"));
+}
+
+#[test]
+fn select_columns() {
+    let stdout = run_script("insert 2 bob bob@x.io\ninsert 1 alice alice@x.io\nselect id\nselect email, username\n");
+    assert!(stdout.contains("db> (1)\n(2)\ndb> "));
+    assert!(stdout.contains("db> (alice@x.io, alice)\n(bob@x.io, bob)\ndb> "));
+}
+
+#[test]
+fn select_distinct() {
+    let stdout = run_script("insert 3 bob b@x.io\ninsert 1 al a@x.io\ninsert 2 bob bob@x.io\ninsert 4 al al@x.io\n\
+                             select distinct username\n");
+    assert!(stdout.ends_with("db> al\nbob\ndb> \n"));
+}
+
+#[test]
+fn runs_script_file() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("dbrs-script-{}.dbrs", std::process::id()));
+    std::fs::write(&path, "-- seed\n.echo on\ninsert 1 a a@x.io\n\nselect\n")?;
+    let output = run(&["-f", path.to_str().unwrap()], ".exit\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout)?, "-- insert 1 a a@x.io\nRow inserted at position 0\n-- select\n(1, a, a@x.io)\n");
+
+    std::fs::write(&path, "insert 1 a a@x.io\ninsert x b b@x.io\nselect\n")?;
+    let output = run(&["-f", path.to_str().unwrap()], "");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr)?.starts_with("Line 2: Error: Illegal insert statement: Invalid id 'x'"));
+    assert!(String::from_utf8(output.stdout)?.ends_with("(1, a, a@x.io)\n"));
+
+    std::fs::write(&path, "bogus statement\n.exit\n")?;
+    assert_eq!(run(&["-f", path.to_str().unwrap()], "").status.code(), Some(1));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn version_and_help() {
+    let output = run(&["--version"], "insert 1 a a@x.io\n");
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), format!("dbrs {}\n", env!("CARGO_PKG_VERSION")));
+
+    // the REPL is not started
+    let output = run(&["--help"], "insert 1 a a@x.io\n");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("Usage: dbrs"));
+    assert!(stdout.contains("--strict"));
+    assert!(stdout.contains("[FILE]"));
+    assert!(!stdout.contains("db> "));
+}
+
+#[test]
+fn opens_file_from_args() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("dbrs-args-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path_arg = path.to_str().unwrap();
+
+    let output = run(&["--page-size", "1024", path_arg], "insert 1 alice alice@example.com\n.stats\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("last_page_free_bytes: 729\n"));
+
+    let output = run(&[path_arg], "select\n");
+    assert!(String::from_utf8(output.stdout)?.contains("(1, alice, alice@example.com)"));
+
+    // the file keeps the page size it was created with
+    let output = run(&["--page-size", "4096", path_arg], "select\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr)?.contains("File uses pages of 1024 bytes"));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn readonly_flag() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("dbrs-readonly-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path_arg = path.to_str().unwrap();
+
+    // a missing file is not created
+    assert!(!run(&["--readonly", path_arg], "").status.success());
+    assert!(!path.exists());
+
+    run(&[path_arg], "insert 1 alice alice@example.com\n");
+    let before = std::fs::read(&path)?;
+    let output = run(&["--readonly", path_arg], "insert 2 bob bob@example.com\ndelete where id = 1\nselect\n.exit\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("db> (1, alice, alice@example.com)\n"));
+    assert_eq!(String::from_utf8(output.stderr)?, "Error inserting row: The table is read-only\nThe table is read-only\n");
+    assert_eq!(std::fs::read(&path)?, before);
+
+    // opening a file again keeps the session read-only
+    let output = run(&["--readonly", path_arg], &format!(".open {}\ninsert 2 bob bob@example.com\n", path_arg));
+    assert!(String::from_utf8(output.stdout)?.contains("Opened"));
+    assert_eq!(String::from_utf8(output.stderr)?, "Error inserting row: The table is read-only\n");
+    assert_eq!(std::fs::read(&path)?, before);
+
+    // importing nothing succeeds, importing rows fails at the first one
+    let import = std::env::temp_dir().join(format!("dbrs-readonly-import-{}.txt", std::process::id()));
+    std::fs::write(&import, "")?;
+    let output = run(&["--readonly", path_arg], &format!(".import {}
+", import.display()));
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout)?.contains("Imported 0 rows"));
+    std::fs::write(&import, "2 bob bob@example.com
+")?;
+    let output = run(&["--readonly", path_arg], &format!(".import {}
+", import.display()));
+    assert_eq!(String::from_utf8(output.stderr)?, "Import stopped at line 1 after inserting 0 rows: The table is read-only\n");
+    assert_eq!(std::fs::read(&path)?, before);
+
+    std::fs::remove_file(&import)?;
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
+
+#[test]
+fn in_memory_flag() {
+    let output = run(&["--in-memory"], "insert 1 alice alice@example.com\nselect\n");
+    assert!(output.status.success());
+    assert!(String::from_utf8(output.stdout).unwrap().contains("(1, alice, alice@example.com)"));
+
+    let output = run(&["--in-memory", "test.db"], "select\n");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("--in-memory cannot be combined with a database file"));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn custom_prompt() {
+    let output = run_script(".prompt >> \ninsert 1 alice alice@example.com\n.prompt \"sql> \"\n");
+    assert_eq!(output, "db> >> Row inserted at position 0\n>> sql> \n");
+
+    let output = run(&["--prompt", "$ "], "select\n");
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "$ $ \n");
+}
+
+#[test]
+fn quiet_flag() {
+    let script = "insert 2 bob bob@example.com\ninsert 1 alice alice@example.com\nselect\ndelete where id = 2\nselect max(id)\n.exit\n";
+    for flag in ["--quiet", "--no-prompt"] {
+        let output = run(&[flag], script);
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8(output.stdout).unwrap(), "(1, alice, alice@example.com)\n(2, bob, bob@example.com)\n1\n");
+    }
+
+    let output = run(&["--quiet"], "insert 1 a a@x.io\ninsert -1 b b@x.io\n");
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("Invalid id '-1'"));
+}
+
+#[test]
+fn tsv_mode() {
+    let output = run(&["--quiet"], ".mode tsv\ninsert 1 alice alice@example.com\nselect\nselect username, id\n");
+    assert_eq!(output.stdout, b"1\talice\talice@example.com\nalice\t1\n");
+}
+
+#[test]
+fn username_index() {
+    let stdout = run_script("insert 3 bob b1@x.io\ninsert 1 al a@x.io\ninsert 2 bob b2@x.io\ncreate index on username\n.indexes\n\
+                             explain select where username = 'bob'\nselect where username = 'bob'\ndrop index on username\n.indexes\n");
+    assert!(stdout.contains("db> Created index on username\ndb> id\nusername\ndb> IndexLookup(username = 'bob')\n\
+                             db> (2, bob, b2@x.io)\n(3, bob, b1@x.io)\ndb> Dropped index on username\ndb> id\ndb> \n"));
+}
+
+#[test]
+fn compact() -> Result<(), Box<dyn Error>> {
+    let path = std::env::temp_dir().join(format!("dbrs-cli-compact-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let path_arg = path.to_str().unwrap();
+
+    let stdout = run_script(&format!("insert 3 cy c@x.io\ninsert 1 al a@x.io\ninsert 2 bo b@x.io\ndelete where id = 2\n.compact {}\n", path_arg));
+    assert!(stdout.contains(&format!("db> Compacted 2 rows into {}\n", path_arg)));
+    let stdout = run_script(&format!(".open {}\nselect physical\n", path_arg));
+    assert!(stdout.contains("(1, al, a@x.io)\n(3, cy, c@x.io)\n"));
+
+    let output = run(&[], &format!(".compact {}\n", path_arg));
+    assert!(String::from_utf8(output.stderr)?.starts_with(&format!("Unable to compact into '{}'", path_arg)));
+
+    std::fs::remove_file(&path)?;
+    Ok(())
+}
@@ -0,0 +1,51 @@
+//! Statements awaited through `AsyncDatabase`. There is no async runtime among the dependencies, so this
+//! file carries a minimal executor that parks the thread until the future wakes it.
+#![cfg(feature = "async")]
+
+use std::future::Future;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::{self, Thread};
+
+use dbrs::nonblocking::{AsyncDatabase, QueryResult};
+use dbrs::row::Row;
+use dbrs::table::Table;
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+fn block_on<F: Future>(future: F) -> F::Output {
+    let waker = Arc::new(Unpark(thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut future = pin!(future);
+    loop {
+        match future.as_mut().poll(&mut cx) {
+            Poll::Ready(output) => return output,
+            Poll::Pending => thread::park(),
+        }
+    }
+}
+
+#[test]
+fn insert_then_select() -> Result<(), String> {
+    let db = AsyncDatabase::new(Table::new());
+    block_on(async {
+        assert_eq!(db.execute("insert 2 bo bo@x.io").await?, QueryResult::Inserted(0));
+        assert_eq!(db.execute("insert 1 al al@x.io").await?, QueryResult::Inserted(1));
+        assert_eq!(db.execute("insert or replace 2 bob bob@x.io").await?, QueryResult::Replaced(0));
+
+        let rows = vec![Row::from_string("1 al al@x.io")?, Row::from_string("2 bob bob@x.io")?];
+        assert_eq!(db.execute("select").await?, QueryResult::Rows(rows));
+        assert_eq!(db.execute("delete where id > 1").await?, QueryResult::Deleted(1));
+        assert_eq!(db.execute("select where id = 1").await?, QueryResult::Rows(vec![Row::from_string("1 al al@x.io")?]));
+        assert!(db.execute("select max(id)").await.is_err());
+        assert!(db.execute("insert x").await.is_err());
+        Ok(())
+    })
+}
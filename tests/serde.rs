@@ -0,0 +1,154 @@
+//! The `serde` impls of `Row`. There is no data format crate among the dependencies, so rows are read
+//! through serde's own `MapDeserializer` and `SeqDeserializer` and written to a small serializer that
+//! records the fields.
+#![cfg(feature = "serde")]
+
+use dbrs::row::Row;
+use serde::de::value::{Error, MapDeserializer, SeqDeserializer};
+use serde::de::{self, IntoDeserializer, Visitor};
+use serde::ser::{self, Impossible, Serialize, SerializeStruct};
+use serde::{forward_to_deserialize_any, Deserialize, Deserializer, Serializer};
+
+#[test]
+fn fields() -> Result<(), Error> {
+    let row = Row::new(7, "al", "al@example.com").map_err(de::Error::custom)?;
+    assert_eq!(Serialize::serialize(&row, Recorder)?, "Row { id: 7, username: al, email: al@example.com }");
+
+    let fields = [("id", Value::Id(7)), ("username", Value::Text("al")), ("email", Value::Text("al@example.com"))];
+    assert_eq!(from_map(&fields)?, row);
+    let values = [Value::Id(7), Value::Text("al"), Value::Text("al@example.com")];
+    assert_eq!(<Row as Deserialize>::deserialize(SeqDeserializer::<_, Error>::new(values.into_iter()))?, row);
+
+    // fields may come in any order
+    let reordered = [("email", Value::Text("al@example.com")), ("id", Value::Id(7)), ("username", Value::Text("al"))];
+    assert_eq!(from_map(&reordered)?, row);
+
+    Ok(())
+}
+
+#[test]
+fn rejects_bad_fields() {
+    let duplicate = [("id", Value::Id(1)), ("id", Value::Id(2)), ("username", Value::Text("al")), ("email", Value::Text("a@b"))];
+    assert_eq!(from_map(&duplicate).unwrap_err().to_string(), "duplicate field `id`");
+
+    let unknown = [("id", Value::Id(1)), ("name", Value::Text("al")), ("email", Value::Text("a@b"))];
+    assert!(from_map(&unknown).unwrap_err().to_string().starts_with("unknown field `name`"));
+
+    let missing = [("id", Value::Id(1)), ("email", Value::Text("a@b"))];
+    assert_eq!(from_map(&missing).unwrap_err().to_string(), "missing field `username`");
+
+    // the fields are checked like those of any other row
+    let invalid = [("id", Value::Id(1)), ("username", Value::Text("al")), ("email", Value::Text("al"))];
+    assert!(from_map(&invalid).is_err());
+}
+
+fn from_map(fields: &[(&'static str, Value)]) -> Result<Row, Error> {
+    <Row as Deserialize>::deserialize(MapDeserializer::new(fields.iter().copied()))
+}
+
+/// A field value as a data format would hand it to a visitor
+#[derive(Clone, Copy)]
+enum Value {
+    Id(u32),
+    Text(&'static str),
+}
+
+impl<'de> Deserializer<'de> for Value {
+    type Error = Error;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, Error> {
+        match self {
+            Value::Id(id) => visitor.visit_u32(id),
+            Value::Text(text) => visitor.visit_borrowed_str(text),
+        }
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string bytes byte_buf option unit
+        unit_struct newtype_struct seq tuple tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl IntoDeserializer<'_, Error> for Value {
+    type Deserializer = Value;
+
+    fn into_deserializer(self) -> Value {
+        self
+    }
+}
+
+/// Writes a struct of integers and strings as `Name { field: value, .. }`, anything else fails
+struct Recorder;
+
+struct StructRecorder {
+    name: &'static str,
+    fields: Vec<String>,
+}
+
+fn unsupported<T>() -> Result<T, Error> {
+    Err(ser::Error::custom("unsupported type"))
+}
+
+impl Serializer for Recorder {
+    type Ok = String;
+    type Error = Error;
+    type SerializeSeq = Impossible<String, Error>;
+    type SerializeTuple = Impossible<String, Error>;
+    type SerializeTupleStruct = Impossible<String, Error>;
+    type SerializeTupleVariant = Impossible<String, Error>;
+    type SerializeMap = Impossible<String, Error>;
+    type SerializeStruct = StructRecorder;
+    type SerializeStructVariant = Impossible<String, Error>;
+
+    fn serialize_u32(self, v: u32) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_str(self, v: &str) -> Result<String, Error> {
+        Ok(v.to_string())
+    }
+
+    fn serialize_struct(self, name: &'static str, len: usize) -> Result<StructRecorder, Error> {
+        Ok(StructRecorder { name, fields: Vec::with_capacity(len) })
+    }
+
+    fn serialize_bool(self, _: bool) -> Result<String, Error> { unsupported() }
+    fn serialize_i8(self, _: i8) -> Result<String, Error> { unsupported() }
+    fn serialize_i16(self, _: i16) -> Result<String, Error> { unsupported() }
+    fn serialize_i32(self, _: i32) -> Result<String, Error> { unsupported() }
+    fn serialize_i64(self, _: i64) -> Result<String, Error> { unsupported() }
+    fn serialize_u8(self, _: u8) -> Result<String, Error> { unsupported() }
+    fn serialize_u16(self, _: u16) -> Result<String, Error> { unsupported() }
+    fn serialize_u64(self, _: u64) -> Result<String, Error> { unsupported() }
+    fn serialize_f32(self, _: f32) -> Result<String, Error> { unsupported() }
+    fn serialize_f64(self, _: f64) -> Result<String, Error> { unsupported() }
+    fn serialize_char(self, _: char) -> Result<String, Error> { unsupported() }
+    fn serialize_bytes(self, _: &[u8]) -> Result<String, Error> { unsupported() }
+    fn serialize_none(self) -> Result<String, Error> { unsupported() }
+    fn serialize_some<T: ?Sized + Serialize>(self, _: &T) -> Result<String, Error> { unsupported() }
+    fn serialize_unit(self) -> Result<String, Error> { unsupported() }
+    fn serialize_unit_struct(self, _: &'static str) -> Result<String, Error> { unsupported() }
+    fn serialize_unit_variant(self, _: &'static str, _: u32, _: &'static str) -> Result<String, Error> { unsupported() }
+    fn serialize_newtype_struct<T: ?Sized + Serialize>(self, _: &'static str, _: &T) -> Result<String, Error> { unsupported() }
+    fn serialize_newtype_variant<T: ?Sized + Serialize>(self, _: &'static str, _: u32, _: &'static str, _: &T) -> Result<String, Error> { unsupported() }
+    fn serialize_seq(self, _: Option<usize>) -> Result<Self::SerializeSeq, Error> { unsupported() }
+    fn serialize_tuple(self, _: usize) -> Result<Self::SerializeTuple, Error> { unsupported() }
+    fn serialize_tuple_struct(self, _: &'static str, _: usize) -> Result<Self::SerializeTupleStruct, Error> { unsupported() }
+    fn serialize_tuple_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeTupleVariant, Error> { unsupported() }
+    fn serialize_map(self, _: Option<usize>) -> Result<Self::SerializeMap, Error> { unsupported() }
+    fn serialize_struct_variant(self, _: &'static str, _: u32, _: &'static str, _: usize) -> Result<Self::SerializeStructVariant, Error> { unsupported() }
+}
+
+impl SerializeStruct for StructRecorder {
+    type Ok = String;
+    type Error = Error;
+
+    fn serialize_field<T: ?Sized + Serialize>(&mut self, key: &'static str, value: &T) -> Result<(), Error> {
+        self.fields.push(format!("{}: {}", key, value.serialize(Recorder)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<String, Error> {
+        Ok(format!("{} {{ {} }}", self.name, self.fields.join(", ")))
+    }
+}
@@ -0,0 +1,62 @@
+//! Spans and events reported to a subscriber. The subscriber is installed for the whole process, so this
+//! file holds a single test.
+#![cfg(feature = "trace")]
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use dbrs::row::Row;
+use dbrs::table::Table;
+use dbrs::trace::{set_subscriber, Record, Subscriber};
+
+#[derive(Default)]
+struct Collector {
+    entered: Mutex<Vec<Record>>,
+    exited: Mutex<Vec<&'static str>>,
+    events: Mutex<Vec<Record>>,
+}
+
+struct Shared(Arc<Collector>);
+
+impl Subscriber for Shared {
+    fn enter(&self, span: &Record) {
+        self.0.entered.lock().unwrap().push(span.clone());
+    }
+
+    fn exit(&self, span: &Record, _elapsed: Duration) {
+        self.0.exited.lock().unwrap().push(span.name);
+    }
+
+    fn event(&self, event: &Record) {
+        self.0.events.lock().unwrap().push(event.clone());
+    }
+}
+
+#[test]
+fn subscriber_captures_spans() -> Result<(), String> {
+    let path = std::env::temp_dir().join(format!("dbrs-trace-{}.db", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let collector = Arc::new(Collector::default());
+    assert!(set_subscriber(Box::new(Shared(Arc::clone(&collector)))).is_ok());
+
+    let mut table = Table::open(&path)?;
+    table.insert_row(&Row::from_string("7 al al@x.io")?)?;
+    table.select_by_id(7)?;
+    table.flush()?;
+    drop(table);
+    Table::open(&path)?;
+
+    let entered = collector.entered.lock().unwrap();
+    let names: Vec<&str> = entered.iter().map(|span| span.name).collect();
+    assert_eq!(names, ["open", "insert_row", "select_by_id", "flush", "open"]);
+    assert_eq!(entered[1].fields, [("id", String::from("7"))]);
+    assert_eq!(entered[3].fields, [("num_pages", String::from("1"))]);
+    assert_eq!(*collector.exited.lock().unwrap(), names);
+
+    let events = collector.events.lock().unwrap();
+    let pages: Vec<(&str, &str)> = events.iter().map(|event| (event.name, event.fields[0].1.as_str())).collect();
+    assert_eq!(pages, [("load_pages", "0"), ("load_pages", "1")]);
+
+    std::fs::remove_file(&path).map_err(|e| e.to_string())?;
+    Ok(())
+}